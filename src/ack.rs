@@ -0,0 +1,88 @@
+use crate::read_x12::{element, tokenize};
+
+// one AK2 / IK5 loop - the partner's verdict on a transaction set we sent
+#[derive(Debug)]
+pub struct TransactionSetAck {
+    pub control_number: String,
+    pub status: String,
+    pub errors: Vec<String>,
+}
+
+// one AK1 ... AK9 group
+#[derive(Debug)]
+pub struct GroupAck {
+    pub functional_identifier: String,
+    pub control_number: String,
+    pub status: String,
+    pub transaction_sets: Vec<TransactionSetAck>,
+}
+
+pub fn status_name(code: &str) -> &str {
+    match code {
+        "A" => "accepted",
+        "E" => "accepted with errors",
+        "P" => "partially accepted",
+        "R" => "rejected",
+        "M" => "rejected, message authentication failed",
+        "W" => "rejected, assurance failed",
+        "X" => "rejected, content could not be decrypted",
+        "" => "no status",
+        _ => "unknown status",
+    }
+}
+
+pub fn is_rejected(code: &str) -> bool {
+    matches!(code, "R" | "M" | "W" | "X")
+}
+
+// reads a 997 or 999 functional acknowledgment; segment and element errors
+// (AK3 / AK4, IK3 / IK4) are kept as text on the transaction set they follow
+pub fn parse_ack(edi: &str) -> Result<Vec<GroupAck>, Box<dyn std::error::Error>> {
+    let (_, segments) = tokenize(edi)?;
+    let mut groups: Vec<GroupAck> = Vec::new();
+
+    for segment in segments.iter() {
+        match segment.id.as_str() {
+            "ST" => match element(segment, 1) {
+                "997" | "999" => {},
+                other => return Err(format!("Expected a 997 or 999 acknowledgment, found a {}.", other).into()),
+            },
+            "AK1" => groups.push(GroupAck {
+                functional_identifier: element(segment, 1).to_string(),
+                control_number: element(segment, 2).to_string(),
+                status: String::new(),
+                transaction_sets: Vec::new(),
+            }),
+            "AK2" => groups.last_mut().ok_or("AK2 found before any AK1.")?
+                .transaction_sets.push(TransactionSetAck {
+                    control_number: element(segment, 2).to_string(),
+                    status: String::new(),
+                    errors: Vec::new(),
+                }),
+            "AK3" | "IK3" | "AK4" | "IK4" | "AK5" | "IK5" => {
+                let transaction_set = groups.last_mut()
+                    .and_then(|group| group.transaction_sets.last_mut())
+                    .ok_or(format!("{} found before any AK2.", segment.id))?;
+
+                match segment.id.as_str() {
+                    "AK3" | "IK3" => transaction_set.errors.push(format!("segment {} at position {}: error {}", element(segment, 1), element(segment, 2), element(segment, 4))),
+                    "AK4" | "IK4" => transaction_set.errors.push(format!("element {}: error {} (value '{}')", element(segment, 1), element(segment, 3), element(segment, 4))),
+                    _ => {
+                        transaction_set.status = element(segment, 1).to_string();
+                        for code in segment.elements.iter().skip(1).filter(|code| !code.is_empty()) {
+                            transaction_set.errors.push(format!("transaction set error {}", code));
+                        }
+                    },
+                }
+            },
+            "AK9" => groups.last_mut().ok_or("AK9 found before any AK1.")?.status = element(segment, 1).to_string(),
+            _ => {},
+        }
+    }
+
+    if groups.is_empty() {
+        return Err("No AK1 group found in the acknowledgment.".into());
+    }
+
+    Ok(groups)
+}
@@ -0,0 +1,159 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+const INPUTS_DIR: &str = "inputs";
+const MANIFEST_FILE: &str = "manifest.json";
+
+// a file kept in the run directory, with the hash it had when the run ended
+#[derive(Serialize, Debug, Clone)]
+pub struct ArchivedFile {
+    // where an input was copied from; outputs were written in place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub file: String,
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RunDocument {
+    pub control_number: i32,
+    pub contract: String,
+    pub purpose: String,
+}
+
+// everything the manifest records besides the files themselves
+#[derive(Serialize, Debug)]
+pub struct RunSummary {
+    pub backend: String,
+    pub partner: String,
+    pub interchange_control_number: String,
+    pub group_control_number: String,
+    pub documents: Vec<RunDocument>,
+    // false when the backend stopped before X12 or the run failed
+    pub sent: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RunManifest<'a> {
+    created: &'a str,
+    #[serde(flatten)]
+    summary: &'a RunSummary,
+    inputs: &'a [ArchivedFile],
+    outputs: Vec<ArchivedFile>,
+}
+
+fn sha256(path: &Path) -> Result<(String, u64), Box<dyn std::error::Error>> {
+    let bytes = fs::read(path)?;
+    let digest = Sha256::digest(&bytes);
+
+    Ok((digest.iter().map(|b| format!("{:02x}", b)).collect(), bytes.len() as u64))
+}
+
+// keeps the directory name portable whatever the contract number holds
+fn sanitize(part: &str) -> String {
+    part.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+        .collect()
+}
+
+// one directory per run - <paths.runs>/<timestamp>_<contract>_<purpose>_<control number> -
+// holding copies of the inputs, every intermediate file, the edi and a manifest
+pub struct RunDir {
+    path: PathBuf,
+    created: String,
+    inputs: RefCell<Vec<ArchivedFile>>,
+}
+
+impl RunDir {
+    pub fn create(root: &Path, name_parts: &[&str]) -> Result<Self, Box<dyn std::error::Error>> {
+        let now = Local::now();
+        let mut name = now.format("%Y%m%dT%H%M%S").to_string();
+
+        for part in name_parts.iter().filter(|part| !part.is_empty()) {
+            name.push('_');
+            name.push_str(sanitize(part).as_str());
+        }
+
+        let path = root.join(name);
+        if path.exists() {
+            return Err(format!("Run directory {} already exists.", path.display()).into());
+        }
+        fs::create_dir_all(path.join(INPUTS_DIR))?;
+
+        Ok(Self {
+            path,
+            created: now.to_rfc3339(),
+            inputs: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    pub fn file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    // copies an input under inputs/<kind>/ and records its hash; the same file is only kept once
+    pub fn archive_input(&self, kind: &str, source: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let file_name = source.file_name().ok_or_else(|| format!("{} is not a file.", source.display()))?;
+        let relative = Path::new(INPUTS_DIR).join(kind).join(file_name);
+        let file = relative.display().to_string();
+
+        if self.inputs.borrow().iter().any(|input| input.file == file) {
+            return Ok(());
+        }
+
+        fs::create_dir_all(self.path.join(INPUTS_DIR).join(kind))?;
+        fs::copy(source, self.path.join(&relative))?;
+
+        let (sha256, bytes) = sha256(self.path.join(&relative).as_path())?;
+        self.inputs.borrow_mut().push(ArchivedFile {
+            source: Some(source.display().to_string()),
+            file,
+            sha256,
+            bytes,
+        });
+
+        Ok(())
+    }
+
+    // hashes everything written next to the inputs and writes manifest.json
+    pub fn write_manifest(&self, summary: &RunSummary) -> Result<(), Box<dyn std::error::Error>> {
+        let mut outputs = Vec::new();
+
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if !entry.file_type()?.is_file() || name == MANIFEST_FILE {
+                continue;
+            }
+
+            let (sha256, bytes) = sha256(entry.path().as_path())?;
+            outputs.push(ArchivedFile { source: None, file: name, sha256, bytes });
+        }
+        outputs.sort_by(|a, b| a.file.cmp(&b.file));
+
+        let inputs = self.inputs.borrow();
+        let manifest = RunManifest {
+            created: self.created.as_str(),
+            summary,
+            inputs: inputs.as_slice(),
+            outputs,
+        };
+
+        fs::write(self.file(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+// stands in for the stedi mappings and translate apis by replaying recorded
+// responses, so the whole run can be exercised without network or an api key.
+// point [stedi] mappings_url and translate_url at the address it listens on.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use oxhttp::Server;
+use oxhttp::model::{HeaderName, Method, Request, Response, Status};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about = "replays recorded stedi responses", long_about = None)]
+struct Args {
+    /// directory holding map.json (or map/<map_id>.json) and translate.json
+    #[clap(short, long, value_parser, default_value = "stub")]
+    responses: String,
+    /// address to listen on
+    #[clap(short, long, value_parser, default_value = "127.0.0.1:8099")]
+    address: String,
+}
+
+fn reply(status: Status, body: String) -> Response {
+    Response::builder(status)
+        .with_header(HeaderName::CONTENT_TYPE, "application/json")
+        .expect("static header value")
+        .with_body(body)
+}
+
+fn error(status: Status, message: &str) -> Response {
+    reply(status, serde_json::json!({ "message": message }).to_string())
+}
+
+// POST /mappings/<map_id>/map -> map/<map_id>.json, falling back to map.json
+// POST /x12/from-json         -> translate.json
+fn recorded_response(responses: &Path, request: &Request) -> Option<PathBuf> {
+    let segments: Vec<&str> = request.url().path().trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        ["mappings", map_id, "map"] => {
+            let specific = responses.join("map").join(format!("{}.json", map_id));
+            Some(if specific.is_file() { specific } else { responses.join("map.json") })
+        },
+        ["x12", "from-json"] => Some(responses.join("translate.json")),
+        _ => None,
+    }
+}
+
+fn handle(responses: &Path, request: &mut Request) -> Response {
+    println!("{} {}", request.method(), request.url().path());
+
+    if request.method() != &Method::POST {
+        return error(Status::METHOD_NOT_ALLOWED, "Only POST is supported.");
+    }
+
+    // the real apis reject requests without a key, so the stub does too
+    if request.header(&HeaderName::AUTHORIZATION).is_none() {
+        return error(Status::UNAUTHORIZED, "Missing Authorization header.");
+    }
+
+    let file = match recorded_response(responses, request) {
+        Some(file) => file,
+        None => return error(Status::NOT_FOUND, "No such endpoint."),
+    };
+
+    match fs::read_to_string(&file) {
+        Ok(body) => reply(Status::OK, body),
+        Err(e) => error(Status::NOT_FOUND, format!("No recorded response at {}: {}", file.display(), e).as_str()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let responses = PathBuf::from(args.responses);
+
+    if !responses.is_dir() {
+        return Err(format!("Recorded responses directory {} does not exist.", responses.display()).into());
+    }
+
+    println!("replaying {} on http://{}", responses.display(), args.address);
+
+    let server = Server::new(move |request| handle(&responses, request));
+    server.listen(args.address.as_str())?;
+
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, NaiveDate};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::contract::{Buyer, ContractBuilder, LineItem};
+use crate::date::DateFormats;
+use crate::dealer_rules::Classifier;
+use crate::diff::*;
+use crate::error::Error;
+use crate::payload::*;
+use crate::price::PriceFormat;
+use crate::purpose::{policy, PurposePolicy};
+use crate::read_csv::*;
+use crate::uom::Units;
+use crate::validate::ValidationReport;
+use crate::x12::Delimiters;
+
+// input kinds, also the folder names inputs are archived under
+pub const CONTRACTS: &str = "contracts";
+pub const BUYERS: &str = "buyers";
+
+// everything needed to build one contract's document - taken from the command
+// line for a single run, or from a row of the manifest in batch mode
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContractJob {
+    pub contract_number: String,
+    pub buyer_file: String,
+    pub start_date: String,
+    pub end_date: String,
+    pub purpose: DocumentType,
+    #[serde(default)]
+    pub new_end_date_if_any: String,
+    #[serde(default)]
+    pub outgoing_contract_number_if_any: String,
+    #[serde(default)]
+    pub previous_contract_file: String,
+    #[serde(default)]
+    pub previous_buyer_file: String,
+}
+
+// csv files that cannot be read are input errors, named so the file can be found
+fn unreadable(path: &Path, e: Box<dyn std::error::Error>) -> Error {
+    Error::Input(format!("Unable to read {}: {}", path.display(), e).into())
+}
+
+fn read<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Vec<T>, Error> {
+    parse_csv::<T>(path).map_err(|e| unreadable(path, e))
+}
+
+
+pub struct DocumentBuilder<'a> {
+    config: &'a Config,
+    price_format: PriceFormat,
+    units: Units,
+    classifier: Classifier,
+    manufacturer: Manufacturer,
+    dates: DateFormats,
+    // the stedi envelope uses the standard delimiters
+    delimiters: Delimiters,
+}
+
+impl<'a> DocumentBuilder<'a> {
+    pub fn new(config: &'a Config) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            price_format: config.get_price_format(),
+            units: config.get_units(),
+            classifier: config.get_dealer_rules().classifier().map_err(Error::Config)?,
+            manufacturer: config.get_manufacturer(),
+            dates: config.get_date_formats(),
+            delimiters: config.get_envelope().map_or_else(Delimiters::default, |envelope| envelope.delimiters()),
+        })
+    }
+
+    // the csv files a job reads, tagged with the directory they come from
+    pub fn input_files(&self, job: &ContractJob) -> Vec<(&'static str, PathBuf)> {
+        if !policy(job.purpose).reads_inputs() {
+            return Vec::new();
+        }
+
+        let mut files = vec![
+            (CONTRACTS, self.config.get_contracts_path(job.contract_number.as_str())),
+            (BUYERS, self.config.get_buyers_path(job.buyer_file.as_str())),
+        ];
+        if !job.previous_contract_file.is_empty() {
+            files.push((CONTRACTS, self.config.get_contracts_path(job.previous_contract_file.as_str())));
+        }
+        if !job.previous_buyer_file.is_empty() {
+            files.push((BUYERS, self.config.get_buyers_path(job.previous_buyer_file.as_str())));
+        }
+
+        files
+    }
+
+    // adds any problems in the job's input files to the report
+    pub fn validate(&self, job: &ContractJob, report: &mut ValidationReport) -> Result<(), Error> {
+        // every input file is checked up front so all problems are reported at once
+        for (kind, path) in self.input_files(job) {
+            match kind {
+                CONTRACTS => report.check_contract_file(path.as_path(), &self.price_format, &self.units, &self.dates, &self.delimiters),
+                _ => report.check_buyer_file(path.as_path(), &self.dates, &self.delimiters),
+            }.map_err(|e| unreadable(path.as_path(), e))?;
+        }
+
+        Ok(())
+    }
+
+    // dates arrive in any of the [dates] formats, on the command line and in the csv files alike
+    fn date(&self, name: &str, value: &str) -> Result<NaiveDate, Error> {
+        self.dates.parse(value).ok_or_else(|| Error::Input(format!("Invalid {} '{}', expected a {} date.", name, value, self.dates.describe()).into()))
+    }
+
+    fn optional_date(&self, name: &str, value: &str) -> Result<Option<NaiveDate>, Error> {
+        match value.trim() {
+            "" => Ok(None),
+            value => self.date(name, value).map(Some),
+        }
+    }
+
+    fn buyer(&self, buyer: EndBuyerData) -> Result<Buyer, Error> {
+        let (group_type, id_type) = self.classifier.classify(&buyer).map_err(|e| Error::Validation(e.to_string()))?;
+        let mut processed = Buyer::new(group_type, buyer.name.as_str(), id_type, buyer.id.as_str())
+            .distributor_reference(buyer.change.as_str());
+
+        if !buyer.address.is_empty() {
+            processed = processed.address(buyer.address.as_str(), buyer.city.as_str(), buyer.state.as_str(), buyer.zipcode.as_str());
+        }
+        if let Some(start) = self.optional_date("buyer start", buyer.start.as_str())? {
+            processed = processed.effective(start);
+        }
+        if let Some(end) = self.optional_date("buyer end", buyer.end.as_str())? {
+            processed = processed.expires(end);
+        }
+
+        Ok(processed)
+    }
+
+    // every row of a part is one pricing tier of its line; the first row names the
+    // line and says what happens to it
+    fn line(&self, rows: &[&ContractData]) -> Result<LineItem, Error> {
+        let first = rows[0];
+        let change = match first.purpose.as_str() {
+            "delete" => LineChange::Delete,
+            "change" => LineChange::Change,
+            _ => LineChange::Add,
+        };
+
+        let mut processed = LineItem::new(first.part.as_str(), first.description.as_str(), self.price_format.apply(first.price)).change(change);
+
+        for (idx, row) in rows.iter().enumerate() {
+            if idx > 0 {
+                processed = processed.tier(self.price_format.apply(row.price));
+            }
+
+            let (uom, quantity) = self.units.resolve_for(row);
+            processed = processed.pack(uom.as_str(), quantity);
+
+            // a tier without its own dates runs for the whole contract
+            if let Some(start) = self.optional_date("line start", row.start.as_str())? {
+                processed = processed.effective(start);
+            }
+            if let Some(end) = self.optional_date("line end", row.end.as_str())? {
+                processed = processed.expires(end);
+            }
+        }
+
+        Ok(processed)
+    }
+
+    // the buyer and contract rows a job carries - the files as they are (with no summary), or
+    // only what changed since the previous snapshots when the purpose allows it
+    fn rows(&self, job: &ContractJob, policy: &PurposePolicy) -> Result<ContractDiff, Error> {
+        if !policy.diff || job.previous_contract_file.is_empty() {
+            return Ok(ContractDiff {
+                agreements: read::<ContractData>(self.config.get_contracts_path(job.contract_number.as_str()).as_path())?,
+                buyers: read::<EndBuyerData>(self.config.get_buyers_path(job.buyer_file.as_str()).as_path())?,
+                summary: Vec::new(),
+            });
+        }
+
+        // without a previous buyer file the buyers are taken as unchanged
+        let previous_buyer_file = match job.previous_buyer_file.as_str() {
+            "" => job.buyer_file.as_str(),
+            previous => previous,
+        };
+
+        Ok(diff_snapshots(
+            &read::<ContractData>(self.config.get_contracts_path(job.previous_contract_file.as_str()).as_path())?,
+            &read::<ContractData>(self.config.get_contracts_path(job.contract_number.as_str()).as_path())?,
+            &read::<EndBuyerData>(self.config.get_buyers_path(previous_buyer_file).as_path())?,
+            &read::<EndBuyerData>(self.config.get_buyers_path(job.buyer_file.as_str()).as_path())?,
+            self.dates.format(Local::now().date_naive()).as_str(),
+        ))
+    }
+
+    // every purpose goes through the same assembly; its policy decides what is read and sent.
+    // a change diffed from snapshots also returns what changed, one line per buyer or part,
+    // for the caller to show; it is empty otherwise
+    pub fn build(&self, job: &ContractJob) -> Result<(Document, Vec<String>), Error> {
+        let policy = policy(job.purpose);
+
+        let mut builder = ContractBuilder::new(job.contract_number.as_str())
+            .purpose(job.purpose)
+            .manufacturer(self.manufacturer.clone())
+            .effective(self.date("start date", job.start_date.as_str())?)
+            .expires(self.date("end date", job.end_date.as_str())?);
+
+        if let Some(new_end_date) = self.optional_date("new end date", job.new_end_date_if_any.as_str())? {
+            builder = builder.new_expiration(new_end_date);
+        }
+        if !job.outgoing_contract_number_if_any.is_empty() {
+            builder = builder.replaces(job.outgoing_contract_number_if_any.as_str());
+        }
+
+        let mut summary = Vec::new();
+        if policy.reads_inputs() {
+            let diff = self.rows(job, policy)?;
+
+            for buyer in diff.buyers.into_iter() {
+                builder = builder.buyer(self.buyer(buyer)?);
+            }
+            for rows in by_part(&diff.agreements) {
+                builder = builder.line(self.line(&rows)?);
+            }
+            summary = diff.summary;
+        }
+
+        Ok((builder.build()?, summary))
+    }
+}
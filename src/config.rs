@@ -1,72 +1,396 @@
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::date::DateFormats;
+use crate::dealer_rules::DealerRules;
+use crate::envelope::Envelope;
+use crate::payload::Manufacturer;
+use crate::price::PriceFormat;
+use crate::rules::RuleSettings;
+use crate::secret::{Secret, SecretSource};
+use crate::stedi::{MAPPINGS_BASE_URL, TRANSLATE_BASE_URL};
+use crate::translate::Backend;
+use crate::uom::Units;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     paths: Paths,
     stedi: Stedi,
+    envelope: Option<Envelope>,
+    pricing: Option<PriceFormat>,
+    units: Option<Units>,
+    dealers: Option<DealerRules>,
+    translator: Option<TranslatorSettings>,
+    manufacturer: Option<Manufacturer>,
+    dates: Option<DateFormats>,
+    rules: Option<RuleSettings>,
+    #[serde(default)]
+    partners: HashMap<String, Partner>,
+    // the --partner profile applied, if any
+    #[serde(skip)]
+    partner: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Paths {
-    base: String,
-    buyers: String,
-    contracts: String,
+    base: PathBuf,
+    buyers: PathBuf,
+    contracts: PathBuf,
+    #[serde(default = "default_control_numbers")]
+    control_numbers: PathBuf,
+    #[serde(default = "default_runs")]
+    runs: PathBuf,
+}
+
+fn default_control_numbers() -> PathBuf {
+    PathBuf::from("control_numbers")
+}
+
+fn default_runs() -> PathBuf {
+    PathBuf::from("runs")
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Stedi {
     map_id: String,
-    api_key: String,
+    // optional so offline backends (native, json) need no credentials, and
+    // only resolved when the stedi translator is built
+    api_key: Option<SecretSource>,
     guide_id: String,
+    #[serde(default = "default_mappings_url")]
+    mappings_url: String,
+    #[serde(default = "default_translate_url")]
+    translate_url: String,
+    #[serde(default = "default_timeout_secs")]
+    timeout_secs: u64,
+    #[serde(default = "default_max_retries")]
+    max_retries: u32,
+    #[serde(default = "default_backoff_ms")]
+    backoff_ms: u64,
+}
+
+fn default_mappings_url() -> String {
+    MAPPINGS_BASE_URL.to_string()
+}
+
+fn default_translate_url() -> String {
+    TRANSLATE_BASE_URL.to_string()
+}
+
+// [partners.<name>] in config.toml - anything set here replaces the top level
+// value when the profile is picked with --partner
+#[derive(Deserialize, Debug, Clone)]
+pub struct Partner {
+    envelope: Option<Envelope>,
+    stedi: Option<PartnerStedi>,
+    translator: Option<TranslatorSettings>,
+    manufacturer: Option<Manufacturer>,
+    pricing: Option<PriceFormat>,
+    units: Option<Units>,
+    dealers: Option<DealerRules>,
+    rules: Option<RuleSettings>,
+}
+
+// the api key and retry settings stay shared, only the routing differs per partner
+#[derive(Deserialize, Debug, Clone)]
+pub struct PartnerStedi {
+    map_id: Option<String>,
+    guide_id: Option<String>,
+    mappings_url: Option<String>,
+    translate_url: Option<String>,
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+// [translator] in config.toml
+#[derive(Deserialize, Debug, Clone)]
+pub struct TranslatorSettings {
+    #[serde(default)]
+    backend: Backend,
+}
+
+const CONFIG_FILE: &str = "config.toml";
+const APP_DIR: &str = "busse-845-edi";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // no --config was given and none of the usual places had a config.toml
+    NotFound(Vec<PathBuf>),
+    Read { path: PathBuf, source: std::io::Error },
+    // position is the 1-based (line, column) and key the [table].key the error points at, when known
+    Parse { path: PathBuf, position: Option<(usize, usize)>, key: Option<String>, message: String },
+    MissingDirectory { name: &'static str, path: PathBuf },
+    UnknownPartner { name: String, known: Vec<String> },
+    // the file parsed, but key holds a value that cannot be used
+    InvalidValue { path: PathBuf, key: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NotFound(searched) => {
+                let searched: Vec<String> = searched.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "No config.toml found, looked in: {}", searched.join(", "))
+            },
+            ConfigError::Read { path, source } => write!(f, "Unable to read {}: {}", path.display(), source),
+            ConfigError::Parse { path, position, key, message } => {
+                write!(f, "{}", path.display())?;
+                if let Some((line, column)) = position {
+                    write!(f, ":{}:{}", line, column)?;
+                }
+                write!(f, ": {}", message.trim())?;
+                match key {
+                    Some(key) => write!(f, " ({})", key),
+                    None => Ok(()),
+                }
+            },
+            ConfigError::MissingDirectory { name, path } => write!(f, "The {} directory {} does not exist (set paths.{} in config.toml).", name, path.display(), name),
+            ConfigError::UnknownPartner { name, known } => match known.is_empty() {
+                true => write!(f, "Partner '{}' is not configured, config.toml has no [partners] profiles.", name),
+                false => write!(f, "Partner '{}' is not configured, expected one of: {}", name, known.join(", ")),
+            },
+            ConfigError::InvalidValue { path, key, message } => write!(f, "Invalid value in {}: {} ({})", path.display(), message, key),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// the working directory first, then $XDG_CONFIG_HOME (or ~/.config) and %APPDATA%
+fn candidate_paths() -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::from(CONFIG_FILE)];
+
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+
+    let app_data = std::env::var_os("APPDATA").filter(|dir| !dir.is_empty()).map(PathBuf::from);
+
+    for dir in [config_home, app_data].into_iter().flatten() {
+        candidates.push(dir.join(APP_DIR).join(CONFIG_FILE));
+    }
+
+    candidates
+}
+
+// 1-based line and column of a byte offset, and the dotted key on that line
+// (prefixed with the table it sits in) so errors point somewhere useful
+fn locate(source: &str, offset: usize) -> (usize, usize, Option<String>) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+
+    let text = source[line_start..].lines().next().unwrap_or_default();
+    let key = text.split_once('=').map(|(key, _)| key.trim().trim_matches('"').to_string());
+
+    // an error on a table header (e.g. a missing field) names the table itself
+    let table = source[..line_start + text.len()].lines().rev()
+        .map(str::trim)
+        .find(|l| l.starts_with('['))
+        .map(|l| l.trim_matches(|c| c == '[' || c == ']').to_string());
+
+    let key = match (table, key) {
+        (Some(table), Some(key)) => Some(format!("{}.{}", table, key)),
+        (None, Some(key)) => Some(key),
+        (table, None) => table,
+    };
+
+    (line, column, key)
 }
 
 impl Config {
-    pub fn new() -> Self {
-        let config_toml_file = std::fs::read_to_string("config.toml");
-        let config_toml = match config_toml_file {
-            Ok(config_toml) => config_toml,
-            Err(e) => panic!("Error reading config.toml: {}", e),
+    // an explicit --config path wins; otherwise the first config.toml found is used.
+    // a partner profile, when named, is laid over the top level settings
+    pub fn load(explicit: Option<&Path>, partner: Option<&str>) -> Result<Self, ConfigError> {
+        let path = match explicit {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let candidates = candidate_paths();
+                match candidates.iter().find(|path| path.is_file()) {
+                    Some(path) => path.clone(),
+                    None => return Err(ConfigError::NotFound(candidates)),
+                }
+            },
         };
 
-        let config: Config = toml::from_str(config_toml.as_str()).unwrap();
-        let base_path = config.paths.base.as_str();
+        let config_toml = std::fs::read_to_string(&path).map_err(|source| ConfigError::Read { path: path.clone(), source })?;
 
-        Self {
-            paths: Paths {
-                base: base_path.to_string(),
-                buyers: format!("{}{}",base_path, config.paths.buyers.as_str()),
-                contracts: format!("{}{}",base_path, config.paths.contracts.as_str()),
-            },
-            stedi: Stedi {
-                map_id: config.stedi.map_id,
-                api_key: config.stedi.api_key,
-                guide_id: config.stedi.guide_id,
+        // only the message and position are kept - the Debug form of a toml error carries the whole file, secrets included
+        let mut config: Config = toml::from_str(config_toml.as_str()).map_err(|e| {
+            let (position, key) = match e.span() {
+                Some(span) => {
+                    let (line, column, key) = locate(config_toml.as_str(), span.start);
+                    (Some((line, column)), key)
+                },
+                None => (None, None),
+            };
+            ConfigError::Parse { path: path.clone(), position, key, message: e.message().to_string() }
+        })?;
+
+        // a relative base is taken from the directory config.toml lives in
+        let base = path.parent().unwrap_or(Path::new("")).join(&config.paths.base);
+
+        config.paths = Paths {
+            buyers: base.join(&config.paths.buyers),
+            contracts: base.join(&config.paths.contracts),
+            control_numbers: base.join(&config.paths.control_numbers),
+            runs: base.join(&config.paths.runs),
+            base,
+        };
+
+        // every envelope is checked, not only the one this run picks
+        let mut envelopes: Vec<(String, &Envelope)> = config.envelope.iter().map(|envelope| ("envelope".to_string(), envelope)).collect();
+        let mut partners: Vec<(&String, &Partner)> = config.partners.iter().collect();
+        partners.sort_by_key(|(name, _)| name.as_str());
+        for (name, partner) in partners {
+            if let Some(envelope) = partner.envelope.as_ref() {
+                envelopes.push((format!("partners.{}.envelope", name), envelope));
+            }
+        }
+        for (table, envelope) in envelopes {
+            envelope.check().map_err(|(key, message)| ConfigError::InvalidValue { path: path.clone(), key: format!("{}.{}", table, key), message })?;
+        }
+
+        for (name, dir) in [("buyers", &config.paths.buyers), ("contracts", &config.paths.contracts)] {
+            if !dir.is_dir() {
+                return Err(ConfigError::MissingDirectory { name, path: dir.clone() });
+            }
+        }
+
+        if let Some(name) = partner {
+            config.apply_partner(name)?;
+        }
+
+        Ok(config)
+    }
+
+    fn apply_partner(&mut self, name: &str) -> Result<(), ConfigError> {
+        let partner = match self.partners.get(name) {
+            Some(partner) => partner.clone(),
+            None => {
+                let mut known: Vec<String> = self.partners.keys().cloned().collect();
+                known.sort();
+                return Err(ConfigError::UnknownPartner { name: name.to_string(), known });
             },
+        };
+
+        if let Some(stedi) = partner.stedi {
+            self.stedi.map_id = stedi.map_id.unwrap_or(self.stedi.map_id.clone());
+            self.stedi.guide_id = stedi.guide_id.unwrap_or(self.stedi.guide_id.clone());
+            self.stedi.mappings_url = stedi.mappings_url.unwrap_or(self.stedi.mappings_url.clone());
+            self.stedi.translate_url = stedi.translate_url.unwrap_or(self.stedi.translate_url.clone());
         }
-    }   
+
+        self.envelope = partner.envelope.or(self.envelope.take());
+        self.translator = partner.translator.or(self.translator.take());
+        self.manufacturer = partner.manufacturer.or(self.manufacturer.take());
+        self.pricing = partner.pricing.or(self.pricing.take());
+        self.units = partner.units.or(self.units.take());
+        self.dealers = partner.dealers.or(self.dealers.take());
+        self.rules = partner.rules.or(self.rules.take());
+        self.partner = Some(name.to_string());
+
+        Ok(())
+    }
+
+    pub fn get_partner(&self) -> Option<&str> {
+        self.partner.as_deref()
+    }
 
     pub fn get_buyers_path(&self, file_name: &str) -> PathBuf {
-        let mut path_buf = PathBuf::new();
-        path_buf.push(&self.paths.buyers.as_str());
-        path_buf.push(format!("{}{}", file_name, ".csv"));
-        
-        path_buf
+        self.paths.buyers.join(format!("{}.csv", file_name))
     }
 
     pub fn get_contracts_path(&self, file_name: &str) -> PathBuf {
-        let mut path_buf = PathBuf::new();
-        path_buf.push(self.paths.contracts.as_str());
-        path_buf.push(format!("{}{}", file_name, ".csv").as_str());
-        
-        path_buf
+        self.paths.contracts.join(format!("{}.csv", file_name))
     }
 
-    pub fn get_stedi_params(&self) -> (&str, &str, &str) {
-        (
+    pub fn get_control_numbers_path(&self) -> PathBuf {
+        self.paths.control_numbers.clone()
+    }
+
+    pub fn get_runs_path(&self) -> PathBuf {
+        self.paths.runs.clone()
+    }
+
+    pub fn get_stedi_params(&self) -> Result<(&str, Secret, &str), Box<dyn std::error::Error>> {
+        let api_key = self.stedi.api_key.as_ref().ok_or("No api_key set in the [stedi] section of config.toml.")?.resolve()?;
+
+        Ok((
             self.stedi.map_id.as_str(),
-            self.stedi.api_key.as_str(),
+            api_key,
             self.stedi.guide_id.as_str(),
+        ))
+    }
+
+    // (mappings, translate) base urls - point both at a stub server to run offline
+    pub fn get_stedi_urls(&self) -> (&str, &str) {
+        (
+            self.stedi.mappings_url.as_str(),
+            self.stedi.translate_url.as_str(),
         )
     }
+
+    // (timeout, max retries, initial backoff) for the stedi client
+    pub fn get_stedi_retry_params(&self) -> (Duration, u32, Duration) {
+        (
+            Duration::from_secs(self.stedi.timeout_secs),
+            self.stedi.max_retries,
+            Duration::from_millis(self.stedi.backoff_ms),
+        )
+    }
+
+    pub fn get_envelope(&self) -> Option<&Envelope> {
+        self.envelope.as_ref()
+    }
+
+    pub fn get_price_format(&self) -> PriceFormat {
+        self.pricing.clone().unwrap_or_default()
+    }
+
+    pub fn get_units(&self) -> Units {
+        self.units.clone().unwrap_or_default()
+    }
+
+    pub fn get_dealer_rules(&self) -> DealerRules {
+        self.dealers.clone().unwrap_or_default()
+    }
+
+    pub fn get_backend(&self) -> Backend {
+        self.translator.as_ref().map_or_else(Backend::default, |translator| translator.backend)
+    }
+
+    pub fn get_manufacturer(&self) -> Manufacturer {
+        self.manufacturer.clone().unwrap_or_default()
+    }
+
+    pub fn get_date_formats(&self) -> DateFormats {
+        self.dates.clone().unwrap_or_default()
+    }
+
+    pub fn get_rules(&self) -> RuleSettings {
+        self.rules.clone().unwrap_or_default()
+    }
 }
@@ -0,0 +1,329 @@
+//! Fluent construction of a single 845 document without going through csv files.
+//!
+//! The builder makes the same decisions as the command line: the manufacturer
+//! is always the first dealer, renew and cancel need a new expiration on the
+//! right side of the current one, and a line without its own dates runs for
+//! the whole contract.
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::error::Error;
+use crate::payload::*;
+use crate::purpose::{policy, NewExpiration, PurposePolicy};
+
+/// The pack a line is priced in when none is given.
+pub const DEFAULT_UOM: &str = "CA";
+pub const DEFAULT_QUANTITY: i32 = 1;
+
+/// A buyer (N1 loop) on the contract.
+#[derive(Debug, Clone)]
+pub struct Buyer {
+    entity: DealerType,
+    name: String,
+    qualifier: IdentifierType,
+    id: String,
+    address: Option<(String, String, String, String)>,
+    distributor_reference: Option<String>,
+    effective: Option<NaiveDate>,
+    expires: Option<NaiveDate>,
+}
+
+impl Buyer {
+    /// A buyer identified by `id` under the `qualifier` scheme, e.g. a DEA number.
+    pub fn new(entity: DealerType, name: &str, qualifier: IdentifierType, id: &str) -> Self {
+        Self {
+            entity,
+            name: name.to_string(),
+            qualifier,
+            id: id.to_string(),
+            address: None,
+            distributor_reference: None,
+            effective: None,
+            expires: None,
+        }
+    }
+
+    pub fn address(mut self, street: &str, city: &str, state: &str, zip: &str) -> Self {
+        self.address = Some((street.to_string(), city.to_string(), state.to_string(), zip.to_string()));
+        self
+    }
+
+    /// The TD reference - only sent when the purpose carries one, i.e. on new contracts.
+    pub fn distributor_reference(mut self, reference: &str) -> Self {
+        self.distributor_reference = Some(reference.to_string());
+        self
+    }
+
+    pub fn effective(mut self, date: NaiveDate) -> Self {
+        self.effective = Some(date);
+        self
+    }
+
+    pub fn expires(mut self, date: NaiveDate) -> Self {
+        self.expires = Some(date);
+        self
+    }
+
+    fn to_dealer(&self, policy: &PurposePolicy) -> Dealer {
+        let mut dealer = Dealer::new(self.entity, self.name.as_str(), self.qualifier, self.id.as_str());
+
+        if let Some((street, city, state, zip)) = &self.address {
+            dealer.set_address(Address::new(street, city, state, zip));
+        }
+        if let (true, Some(reference)) = (policy.distributor_reference, &self.distributor_reference) {
+            dealer.add_reference(ReferenceType::AddDistributor, reference);
+        }
+        if let Some(date) = self.effective {
+            dealer.add_datetime(Datetime::new(DatetimeType::AgreementEffective, date));
+        }
+        if let Some(date) = self.expires {
+            dealer.add_datetime(Datetime::new(DatetimeType::AgreementExpiration, date));
+        }
+
+        dealer
+    }
+}
+
+/// One price of a line and the window it applies in (CTP / DTM).
+#[derive(Debug, Clone)]
+struct Tier {
+    price: Decimal,
+    uom: String,
+    quantity: i32,
+    effective: Option<NaiveDate>,
+    expires: Option<NaiveDate>,
+}
+
+/// A priced part (LIN) on the contract, with one or more pricing tiers.
+#[derive(Debug, Clone)]
+pub struct LineItem {
+    part: String,
+    description: String,
+    change: LineChange,
+    // never empty, the pack and date setters apply to the last tier
+    tiers: Vec<Tier>,
+}
+
+impl LineItem {
+    /// A line added to the contract, priced per [`DEFAULT_UOM`] of [`DEFAULT_QUANTITY`].
+    pub fn new(part: &str, description: &str, price: Decimal) -> Self {
+        Self {
+            part: part.to_string(),
+            description: description.to_string(),
+            change: LineChange::Add,
+            tiers: vec![Tier {
+                price,
+                uom: DEFAULT_UOM.to_string(),
+                quantity: DEFAULT_QUANTITY,
+                effective: None,
+                expires: None,
+            }],
+        }
+    }
+
+    fn last_tier(&mut self) -> &mut Tier {
+        let last = self.tiers.len() - 1;
+        &mut self.tiers[last]
+    }
+
+    pub fn change(mut self, change: LineChange) -> Self {
+        self.change = change;
+        self
+    }
+
+    /// Starts another pricing tier in the same pack as the one before, e.g. a
+    /// price increase mid-contract: `.expires(june_30).tier(new_price).effective(july_1)`.
+    pub fn tier(mut self, price: Decimal) -> Self {
+        let previous = self.last_tier().clone();
+        self.tiers.push(Tier { price, effective: None, expires: None, ..previous });
+        self
+    }
+
+    pub fn pack(mut self, uom: &str, quantity: i32) -> Self {
+        let tier = self.last_tier();
+        tier.uom = uom.to_uppercase();
+        tier.quantity = quantity;
+        self
+    }
+
+    /// Defaults to the contract's effective date.
+    pub fn effective(mut self, date: NaiveDate) -> Self {
+        self.last_tier().effective = Some(date);
+        self
+    }
+
+    /// Defaults to the contract's expiration.
+    pub fn expires(mut self, date: NaiveDate) -> Self {
+        self.last_tier().expires = Some(date);
+        self
+    }
+}
+
+/// Builds one document holding one contract.
+///
+/// ```
+/// use busse_845_edi_v2::contract::{Buyer, ContractBuilder, LineItem};
+/// use busse_845_edi_v2::payload::{DealerType, DocumentType, IdentifierType, Payload};
+/// use chrono::NaiveDate;
+/// use rust_decimal::Decimal;
+///
+/// let document = ContractBuilder::new("C100")
+///     .purpose(DocumentType::New)
+///     .effective(NaiveDate::from_ymd_opt(2023, 1, 1).unwrap())
+///     .expires(NaiveDate::from_ymd_opt(2023, 12, 31).unwrap())
+///     .buyer(Buyer::new(DealerType::Distributor, "ACME HOSPITAL", IdentifierType::Dea, "9988"))
+///     .line(LineItem::new("775", "GAUZE PAD", Decimal::new(1234, 2)).pack("BX", 10))
+///     .build()?;
+///
+/// let mut payload = Payload::new();
+/// payload.add_document(document);
+/// # Ok::<(), busse_845_edi_v2::error::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContractBuilder {
+    contract_id: String,
+    purpose: DocumentType,
+    manufacturer: Manufacturer,
+    effective: Option<NaiveDate>,
+    expires: Option<NaiveDate>,
+    new_expiration: Option<NaiveDate>,
+    replaces: Option<String>,
+    buyers: Vec<Buyer>,
+    lines: Vec<LineItem>,
+}
+
+impl ContractBuilder {
+    /// A new contract from the default manufacturer.
+    pub fn new(contract_id: &str) -> Self {
+        Self {
+            contract_id: contract_id.to_string(),
+            purpose: DocumentType::New,
+            manufacturer: Manufacturer::default(),
+            effective: None,
+            expires: None,
+            new_expiration: None,
+            replaces: None,
+            buyers: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn purpose(mut self, purpose: DocumentType) -> Self {
+        self.purpose = purpose;
+        self
+    }
+
+    pub fn manufacturer(mut self, manufacturer: Manufacturer) -> Self {
+        self.manufacturer = manufacturer;
+        self
+    }
+
+    pub fn effective(mut self, date: NaiveDate) -> Self {
+        self.effective = Some(date);
+        self
+    }
+
+    /// The current expiration of the contract.
+    pub fn expires(mut self, date: NaiveDate) -> Self {
+        self.expires = Some(date);
+        self
+    }
+
+    /// The expiration after this document - required to renew or cancel.
+    pub fn new_expiration(mut self, date: NaiveDate) -> Self {
+        self.new_expiration = Some(date);
+        self
+    }
+
+    /// The contract this one replaces, sent as a ZZ reference on new and resubmitted contracts.
+    pub fn replaces(mut self, contract_id: &str) -> Self {
+        self.replaces = Some(contract_id.to_string());
+        self
+    }
+
+    pub fn buyer(mut self, buyer: Buyer) -> Self {
+        self.buyers.push(buyer);
+        self
+    }
+
+    /// Lines are numbered in the order they are added.
+    pub fn line(mut self, line: LineItem) -> Self {
+        self.lines.push(line);
+        self
+    }
+
+    /// Fails with [`Error::Validation`] when the dates do not fit the purpose.
+    ///
+    /// What ends up in the document - references, buyers, lines - is decided by
+    /// the purpose's [`PurposePolicy`]; anything the purpose does not carry is ignored.
+    pub fn build(self) -> Result<Document, Error> {
+        let policy = policy(self.purpose);
+        let effective = self.effective.ok_or_else(|| Error::Validation(format!("Contract {} has no effective date.", self.contract_id)))?;
+        let expires = self.expires.ok_or_else(|| Error::Validation(format!("Contract {} has no expiration date.", self.contract_id)))?;
+
+        match (policy.new_expiration, self.new_expiration) {
+            (NewExpiration::Later | NewExpiration::Earlier, None) => {
+                return Err(Error::Validation(format!("New end date is required for {} purposes.", self.purpose.name())));
+            },
+            (NewExpiration::Later, Some(new_expiration)) if expires > new_expiration => {
+                return Err(Error::Validation("New end date must be greater than current end date".to_string()));
+            },
+            (NewExpiration::Earlier, Some(new_expiration)) if expires < new_expiration => {
+                return Err(Error::Validation("New end date must be less than current end date".to_string()));
+            },
+            _ => {},
+        }
+
+        let mut document = Document::new(self.purpose);
+        document.add_datetime(Datetime::new(DatetimeType::ContractEffective, effective));
+
+        match self.new_expiration {
+            Some(new_expiration) => {
+                document.add_datetime(Datetime::new(DatetimeType::ContractExpiration, new_expiration));
+                document.add_datetime(Datetime::new(DatetimeType::ContractPriorExpiration, expires));
+            },
+            None => document.add_datetime(Datetime::new(DatetimeType::ContractExpiration, expires)),
+        }
+
+        let mut contract = Contract::new(self.contract_id.as_str(), &self.manufacturer);
+
+        if let (true, Some(replaces)) = (policy.outgoing_reference, &self.replaces) {
+            contract.add_reference(ReferenceType::MutuallyDefined, replaces);
+        }
+
+        if policy.dealers {
+            for buyer in self.buyers.iter() {
+                contract.add_dealer(buyer.to_dealer(policy));
+            }
+        }
+
+        let lines = match policy.lines {
+            true => self.lines.as_slice(),
+            false => &[],
+        };
+
+        for (idx, line) in lines.iter().enumerate() {
+            let line_number = i32::try_from(idx + 1).map_err(|_| Error::Validation(format!("Contract {} has too many lines.", self.contract_id)))?;
+
+            let mut agreement = Agreement::new(&line_number, line.change.to_string().as_str());
+            agreement.add_detail(line.description.as_str());
+            agreement.add_line(&line_number, line.part.as_str());
+            for tier in line.tiers.iter() {
+                agreement.add_pricing(Pricing::new(
+                    tier.price,
+                    tier.quantity,
+                    tier.uom.as_str(),
+                    tier.effective.unwrap_or(effective),
+                    tier.expires.unwrap_or(expires),
+                ));
+            }
+
+            contract.add_agreement(agreement);
+        }
+
+        document.add_contract(contract);
+
+        Ok(document)
+    }
+}
@@ -1,15 +1,266 @@
-use nanoid::nanoid;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
 
-const NUMS: [char; 9] = ['1', '2', '3', '4', '5', '6', '7', '8', '9'];
+use chrono::Local;
+use serde::{Deserialize, Serialize};
 
-pub struct ControlNumber;
+// X12 control numbers are at most 9 digits, the sequences roll back over to 1
+const MAX_CONTROL_NUMBER: i32 = 999_999_999;
 
-impl ControlNumber {
-    pub fn create() -> String {
-        nanoid!(9, &NUMS)
+pub enum Sequence {
+    Interchange,
+    Group,
+    TransactionSet,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Sequences {
+    interchange: i32,
+    group: i32,
+    transaction_set: i32,
+}
+
+impl Sequences {
+    fn advance(&mut self, sequence: Sequence) -> i32 {
+        let current = match sequence {
+            Sequence::Interchange => &mut self.interchange,
+            Sequence::Group => &mut self.group,
+            Sequence::TransactionSet => &mut self.transaction_set,
+        };
+
+        *current = if *current >= MAX_CONTROL_NUMBER { 1 } else { *current + 1 };
+
+        *current
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub partner: String,
+    pub interchange: String,
+    pub group: String,
+    pub transaction_set: i32,
+    pub contract: String,
+    pub purpose: String,
+    pub output_file: String,
+}
+
+impl HistoryEntry {
+    pub fn new(partner: &str, interchange: &str, group: &str, transaction_set: i32, contract: &str, purpose: &str, output_file: &str) -> Self {
+        Self {
+            timestamp: Local::now().to_rfc3339(),
+            partner: partner.to_string(),
+            interchange: interchange.to_string(),
+            group: group.to_string(),
+            transaction_set,
+            contract: contract.to_string(),
+            purpose: purpose.to_string(),
+            output_file: output_file.to_string(),
+        }
+    }
+}
+
+// file-backed sequences for ISA13, GS06 and ST02, one set of files per partner:
+//   <partner>.json         - last issued number of each sequence
+//   <partner>.history.csv  - every issued transaction set and where it went
+//   <partner>.lock         - held exclusively while either file is touched
+pub struct ControlNumberRegistry {
+    partner: String,
+    sequences: PathBuf,
+    history: PathBuf,
+    lock: PathBuf,
+}
+
+impl ControlNumberRegistry {
+    pub fn open(dir: &Path, partner: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        fs::create_dir_all(dir)?;
+
+        let partner: String = partner.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+
+        Ok(Self {
+            sequences: dir.join(format!("{}.json", partner)),
+            history: dir.join(format!("{}.history.csv", partner)),
+            lock: dir.join(format!("{}.lock", partner)),
+            partner,
+        })
+    }
+
+    // picks up the sequences and history another registry in the same directory left
+    // behind, e.g. under an older naming, unless this one has numbers of its own already
+    pub fn adopt(&self, previous: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let previous = Self::open(self.sequences.parent().unwrap_or(Path::new("")), previous)?;
+        if previous.partner == self.partner {
+            return Ok(());
+        }
+
+        let _lock = self.acquire()?;
+        if self.sequences.exists() || !previous.sequences.exists() {
+            return Ok(());
+        }
+
+        let _previous_lock = previous.acquire()?;
+        if previous.history.exists() {
+            fs::copy(&previous.history, &self.history)?;
+        }
+        fs::copy(&previous.sequences, &self.sequences)?;
+
+        Ok(())
+    }
+
+    pub fn partner(&self) -> &str {
+        self.partner.as_str()
+    }
+
+    // the lock is released when the returned file is dropped
+    fn acquire(&self) -> Result<File, Box<dyn std::error::Error>> {
+        let lock = OpenOptions::new().create(true).truncate(false).write(true).open(&self.lock)?;
+        lock.lock()?;
+
+        Ok(lock)
+    }
+
+    pub fn next(&self, sequence: Sequence) -> Result<i32, Box<dyn std::error::Error>> {
+        let _lock = self.acquire()?;
+
+        let mut sequences: Sequences = match fs::read_to_string(&self.sequences) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Sequences::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let next = sequences.advance(sequence);
+
+        // write then rename so a crash never leaves a half written sequence file
+        let staged = self.sequences.with_extension("json.tmp");
+        fs::write(&staged, serde_json::to_string_pretty(&sequences)?)?;
+        fs::rename(&staged, &self.sequences)?;
+
+        Ok(next)
     }
 
-    pub fn to_i32(control_number: &str) -> i32 {
-        control_number.parse::<i32>().unwrap()
+    pub fn record(&self, entry: &HistoryEntry) -> Result<(), Box<dyn std::error::Error>> {
+        let _lock = self.acquire()?;
+
+        let exists = self.history.exists();
+        let file = OpenOptions::new().create(true).append(true).open(&self.history)?;
+
+        let mut writer = csv::WriterBuilder::new().has_headers(!exists).from_writer(file);
+        writer.serialize(entry)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    // every transaction set recorded for the partner, oldest first
+    pub fn history(&self) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+        let _lock = self.acquire()?;
+
+        if !self.history.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for entry in csv::Reader::from_path(&self.history)?.deserialize() {
+            entries.push(entry?);
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an empty directory per test so tests can run in parallel
+    fn registry_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("busse-845-control-numbers-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn sequences_roll_over_after_nine_digits() {
+        let mut sequences = Sequences { interchange: MAX_CONTROL_NUMBER - 1, ..Sequences::default() };
+
+        assert_eq!(sequences.advance(Sequence::Interchange), MAX_CONTROL_NUMBER);
+        assert_eq!(sequences.advance(Sequence::Interchange), 1);
+        assert_eq!(sequences.advance(Sequence::Group), 1);
+    }
+
+    #[test]
+    fn a_rolled_over_sequence_is_saved() {
+        let dir = registry_dir("rollover");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("owens.json"), format!(r#"{{"interchange": {}, "group": 7, "transaction_set": 7}}"#, MAX_CONTROL_NUMBER)).unwrap();
+
+        let registry = ControlNumberRegistry::open(&dir, "owens").unwrap();
+        assert_eq!(registry.next(Sequence::Interchange).unwrap(), 1);
+        assert_eq!(registry.next(Sequence::Interchange).unwrap(), 2);
+        assert_eq!(registry.next(Sequence::Group).unwrap(), 8);
+    }
+
+    #[test]
+    fn numbers_carry_on_across_opens() {
+        let dir = registry_dir("reopen");
+
+        let registry = ControlNumberRegistry::open(&dir, "owens").unwrap();
+        assert_eq!(registry.next(Sequence::Interchange).unwrap(), 1);
+        assert_eq!(registry.next(Sequence::TransactionSet).unwrap(), 1);
+
+        let registry = ControlNumberRegistry::open(&dir, "owens").unwrap();
+        assert_eq!(registry.next(Sequence::Interchange).unwrap(), 2);
+        assert_eq!(registry.next(Sequence::Group).unwrap(), 1);
+        assert_eq!(registry.next(Sequence::TransactionSet).unwrap(), 2);
+
+        // another partner keeps its own numbers
+        let other = ControlNumberRegistry::open(&dir, "medline").unwrap();
+        assert_eq!(other.next(Sequence::Interchange).unwrap(), 1);
+    }
+
+    #[test]
+    fn history_reads_back_what_was_recorded() {
+        let dir = registry_dir("history");
+        let registry = ControlNumberRegistry::open(&dir, "owens").unwrap();
+        assert!(registry.history().unwrap().is_empty());
+
+        registry.record(&HistoryEntry::new("owens", "000000001", "1", 1, "C100", "new", "runs/a/output.edi")).unwrap();
+        registry.record(&HistoryEntry::new("owens", "000000002", "2", 2, "C200", "change", "runs/b/output.edi")).unwrap();
+
+        let history = ControlNumberRegistry::open(&dir, "owens").unwrap().history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].interchange.as_str(), history[0].transaction_set, history[0].contract.as_str()), ("000000001", 1, "C100"));
+        assert_eq!((history[1].group.as_str(), history[1].purpose.as_str(), history[1].output_file.as_str()), ("2", "change", "runs/b/output.edi"));
+    }
+
+    #[test]
+    fn partner_names_are_safe_file_names() {
+        let dir = registry_dir("names");
+        let registry = ControlNumberRegistry::open(&dir, "../owens & minor/ZZ").unwrap();
+        assert_eq!(registry.partner(), "___owens___minor_ZZ");
+
+        registry.next(Sequence::Interchange).unwrap();
+        assert!(dir.join("___owens___minor_ZZ.json").exists());
+        assert_eq!(ControlNumberRegistry::open(&dir, "01-123456789").unwrap().partner(), "01-123456789");
+    }
+
+    #[test]
+    fn an_older_sequence_is_adopted_once() {
+        let dir = registry_dir("adopt");
+        let old = ControlNumberRegistry::open(&dir, "123456789").unwrap();
+        old.next(Sequence::Interchange).unwrap();
+        old.record(&HistoryEntry::new("123456789", "000000001", "1", 1, "C100", "new", "output.edi")).unwrap();
+
+        let registry = ControlNumberRegistry::open(&dir, "01-123456789").unwrap();
+        registry.adopt("123456789").unwrap();
+        assert_eq!(registry.next(Sequence::Interchange).unwrap(), 2);
+        assert_eq!(registry.history().unwrap().len(), 1);
+
+        // its own numbers win from then on
+        registry.adopt("123456789").unwrap();
+        assert_eq!(registry.next(Sequence::Interchange).unwrap(), 3);
     }
 }
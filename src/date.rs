@@ -0,0 +1,204 @@
+use chrono::{Datelike, Days, NaiveDate};
+use serde::{Deserialize, Serializer};
+
+// X12 dates (DTM02) are CCYYMMDD
+pub const X12_DATE_FORMAT: &str = "%Y%m%d";
+// the payload json keeps YYYY-MM-DD, the format the stedi map reads and turns into CCYYMMDD
+pub const PAYLOAD_DATE_FORMAT: &str = "%Y-%m-%d";
+
+// the last day excel can hold, 9999-12-31
+const MAX_EXCEL_SERIAL: u64 = 2_958_465;
+// excel serial 0, 1899-12-30, in days from 0001-01-01
+const EXCEL_EPOCH_DAYS_FROM_CE: i32 = 693_594;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    #[serde(rename = "YYYYMMDD")]
+    Compact,
+    #[serde(rename = "YYYY-MM-DD")]
+    Iso,
+    #[serde(rename = "MM/DD/YYYY")]
+    Us,
+    // days since 1899-12-30, what a date cell turns into when a sheet is saved as csv
+    #[serde(rename = "excel")]
+    ExcelSerial,
+}
+
+impl DateFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DateFormat::Compact => "YYYYMMDD",
+            DateFormat::Iso => "YYYY-MM-DD",
+            DateFormat::Us => "MM/DD/YYYY",
+            DateFormat::ExcelSerial => "excel serial",
+        }
+    }
+
+    // chrono accepts unpadded fields, so the shape of the value is checked first
+    pub fn parse(&self, value: &str) -> Option<NaiveDate> {
+        let digits = |part: &str, len: usize| part.len() == len && part.chars().all(|c| c.is_ascii_digit());
+
+        match self {
+            DateFormat::Compact if digits(value, 8) => NaiveDate::parse_from_str(value, "%Y%m%d").ok(),
+            DateFormat::Iso if value.len() == 10 => NaiveDate::parse_from_str(value, "%Y-%m-%d").ok(),
+            DateFormat::Us => match value.split('/').collect::<Vec<&str>>().as_slice() {
+                [month, day, year] if (1..=2).contains(&month.len()) && (1..=2).contains(&day.len()) && digits(year, 4) => {
+                    NaiveDate::parse_from_str(value, "%m/%d/%Y").ok()
+                },
+                _ => None,
+            },
+            DateFormat::ExcelSerial => {
+                // a date-time cell carries the time as a fraction, which is dropped
+                let days = value.split_once('.').map_or(value, |(days, _)| days);
+                if days.is_empty() || days.len() > 7 || !days.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+
+                match days.parse::<u64>().ok()? {
+                    // excel counts a 1900-02-29 that never was, so serials before it are a day off
+                    serial @ 1..=59 => NaiveDate::from_ymd_opt(1899, 12, 31)?.checked_add_days(Days::new(serial)),
+                    serial @ 61..=MAX_EXCEL_SERIAL => NaiveDate::from_ymd_opt(1899, 12, 30)?.checked_add_days(Days::new(serial)),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    pub fn format(&self, date: NaiveDate) -> String {
+        match self {
+            DateFormat::Compact => date.format("%Y%m%d").to_string(),
+            DateFormat::Iso => date.format("%Y-%m-%d").to_string(),
+            DateFormat::Us => date.format("%m/%d/%Y").to_string(),
+            DateFormat::ExcelSerial => match date.num_days_from_ce() - EXCEL_EPOCH_DAYS_FROM_CE {
+                // the same missing 1900-02-29 as in parse
+                days @ ..=60 => (days - 1).to_string(),
+                days => days.to_string(),
+            },
+        }
+    }
+}
+
+// [dates] in config.toml - the formats dates may arrive in on the command line,
+// in manifests and in the csv files, tried in order
+#[derive(Deserialize, Debug, Clone)]
+pub struct DateFormats {
+    #[serde(default = "default_formats")]
+    formats: Vec<DateFormat>,
+}
+
+fn default_formats() -> Vec<DateFormat> {
+    vec![DateFormat::Compact, DateFormat::Iso]
+}
+
+impl Default for DateFormats {
+    fn default() -> Self {
+        Self {
+            formats: default_formats(),
+        }
+    }
+}
+
+impl DateFormats {
+    pub fn parse(&self, value: &str) -> Option<NaiveDate> {
+        let value = value.trim();
+
+        self.formats.iter().find_map(|format| format.parse(value))
+    }
+
+    // e.g. "YYYYMMDD or YYYY-MM-DD", for error messages
+    pub fn describe(&self) -> String {
+        let names: Vec<&str> = self.formats.iter().map(|format| format.name()).collect();
+
+        match names.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => "no date format configured".to_string(),
+        }
+    }
+
+    // dates written back into csv rows (e.g. a removed buyer's expiry) use the first format
+    pub fn format(&self, date: NaiveDate) -> String {
+        self.formats.first().unwrap_or(&DateFormat::Compact).format(date)
+    }
+}
+
+pub fn serialize_payload_date<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(date.format(PAYLOAD_DATE_FORMAT).to_string().as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn formats(formats: Vec<DateFormat>) -> DateFormats {
+        DateFormats { formats }
+    }
+
+    #[test]
+    fn each_format_reads_its_own_shape_only() {
+        assert_eq!(DateFormat::Compact.parse("20230115"), Some(date(2023, 1, 15)));
+        assert_eq!(DateFormat::Compact.parse("2023115"), None);
+        assert_eq!(DateFormat::Iso.parse("2023-01-15"), Some(date(2023, 1, 15)));
+        assert_eq!(DateFormat::Iso.parse("2023-1-15"), None);
+        assert_eq!(DateFormat::Us.parse("1/5/2023"), Some(date(2023, 1, 5)));
+        assert_eq!(DateFormat::Us.parse("01/05/23"), None);
+        assert_eq!(DateFormat::Compact.parse("20230230"), None);
+    }
+
+    #[test]
+    fn excel_serials_count_from_1899_12_30() {
+        assert_eq!(DateFormat::ExcelSerial.parse("45292"), Some(date(2024, 1, 1)));
+        assert_eq!(DateFormat::ExcelSerial.parse("45292.75"), Some(date(2024, 1, 1)));
+        assert_eq!(DateFormat::ExcelSerial.parse("61"), Some(date(1900, 3, 1)));
+        assert_eq!(DateFormat::ExcelSerial.parse("1"), Some(date(1900, 1, 1)));
+        assert_eq!(DateFormat::ExcelSerial.parse("2958465"), Some(date(9999, 12, 31)));
+    }
+
+    #[test]
+    fn excel_serials_outside_the_calendar_are_rejected() {
+        // 60 is the 1900-02-29 excel believes in
+        for serial in ["0", "60", "2958466", "-1", "", "1e5", "12345678"] {
+            assert_eq!(DateFormat::ExcelSerial.parse(serial), None, "{}", serial);
+        }
+    }
+
+    #[test]
+    fn excel_serials_format_back_to_the_same_number() {
+        for serial in ["1", "59", "61", "45292"] {
+            let parsed = DateFormat::ExcelSerial.parse(serial).unwrap();
+            assert_eq!(DateFormat::ExcelSerial.format(parsed), serial);
+        }
+    }
+
+    #[test]
+    fn formats_are_tried_in_order() {
+        let formats = formats(vec![DateFormat::Us, DateFormat::ExcelSerial, DateFormat::Iso]);
+
+        assert_eq!(formats.parse(" 12/31/2023 "), Some(date(2023, 12, 31)));
+        assert_eq!(formats.parse("45291"), Some(date(2023, 12, 31)));
+        assert_eq!(formats.parse("2023-12-31"), Some(date(2023, 12, 31)));
+        assert_eq!(formats.parse("20231231"), None);
+        assert_eq!(formats.format(date(2023, 12, 31)), "12/31/2023");
+        assert_eq!(formats.describe(), "MM/DD/YYYY, excel serial or YYYY-MM-DD");
+    }
+
+    #[test]
+    fn defaults_accept_compact_and_iso() {
+        let formats = DateFormats::default();
+
+        assert_eq!(formats.parse("20230101"), formats.parse("2023-01-01"));
+        assert_eq!(formats.parse("01/01/2023"), None);
+        assert_eq!(formats.describe(), "YYYYMMDD or YYYY-MM-DD");
+    }
+
+    #[test]
+    fn multibyte_input_is_rejected_without_panicking() {
+        assert_eq!(DateFormats::default().parse("２０２３０１０１"), None);
+        assert_eq!(DateFormats::default().parse("2023-01-0é"), None);
+    }
+}
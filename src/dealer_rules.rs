@@ -0,0 +1,221 @@
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::payload::{DealerType, IdentifierType};
+use crate::read_csv::EndBuyerData;
+
+// a rule matches on the exact buyer name (case insensitive), on a regex over
+// the buyer id, or on both when both are given
+#[derive(Deserialize, Debug, Clone)]
+pub struct DealerRule {
+    pub name: Option<String>,
+    pub id_pattern: Option<String>,
+    pub entity: DealerType,
+    pub qualifier: IdentifierType,
+}
+
+// [dealers] in config.toml - the first matching rule wins, buyers no rule
+// matches get the fallback entity code and qualifier
+#[derive(Deserialize, Debug, Clone)]
+pub struct DealerRules {
+    #[serde(default = "default_entity")]
+    pub entity: DealerType,
+    #[serde(default = "default_qualifier")]
+    pub qualifier: IdentifierType,
+    #[serde(default = "default_rules")]
+    pub rules: Vec<DealerRule>,
+}
+
+fn default_entity() -> DealerType {
+    DealerType::EndUser
+}
+
+fn default_qualifier() -> IdentifierType {
+    IdentifierType::VendorDefined
+}
+
+// the GPOs we classified before the rules table existed
+fn default_rules() -> Vec<DealerRule> {
+    ["MEDASSETS", "PREMIER HOSPITALS"].iter()
+        .map(|name| DealerRule {
+            name: Some(name.to_string()),
+            id_pattern: None,
+            entity: DealerType::BuyingGroup,
+            qualifier: IdentifierType::Hin,
+        })
+        .collect()
+}
+
+impl Default for DealerRules {
+    fn default() -> Self {
+        Self {
+            entity: default_entity(),
+            qualifier: default_qualifier(),
+            rules: default_rules(),
+        }
+    }
+}
+
+pub struct Classifier {
+    rules: Vec<(DealerRule, Option<Regex>)>,
+    entity: DealerType,
+    qualifier: IdentifierType,
+}
+
+impl DealerRules {
+    pub fn classifier(&self) -> Result<Classifier, Box<dyn std::error::Error>> {
+        let mut rules = Vec::new();
+
+        for rule in self.rules.iter() {
+            if rule.name.is_none() && rule.id_pattern.is_none() {
+                return Err("Every [[dealers.rules]] entry needs a name or an id_pattern.".into());
+            }
+
+            let pattern = match rule.id_pattern.as_deref() {
+                Some(pattern) => Some(Regex::new(pattern).map_err(|e| format!("Invalid id_pattern '{}': {}", pattern, e))?),
+                None => None,
+            };
+
+            rules.push((rule.clone(), pattern));
+        }
+
+        Ok(Classifier {
+            rules,
+            entity: self.entity,
+            qualifier: self.qualifier,
+        })
+    }
+}
+
+impl Classifier {
+    fn matches(rule: &DealerRule, pattern: &Option<Regex>, buyer: &EndBuyerData) -> bool {
+        let name = rule.name.as_deref().is_none_or(|name| name.eq_ignore_ascii_case(buyer.name.trim()));
+        let id = pattern.as_ref().is_none_or(|pattern| pattern.is_match(buyer.id.trim()));
+
+        name && id
+    }
+
+    // entity and qualifier columns in the buyer csv override whatever the rules say
+    pub fn classify(&self, buyer: &EndBuyerData) -> Result<(DealerType, IdentifierType), Box<dyn std::error::Error>> {
+        let (entity, qualifier) = self.rules.iter()
+            .find(|(rule, pattern)| Self::matches(rule, pattern, buyer))
+            .map_or((self.entity, self.qualifier), |(rule, _)| (rule.entity, rule.qualifier));
+
+        let entity = match buyer.entity.trim() {
+            "" => entity,
+            code => code.parse()?,
+        };
+        let qualifier = match buyer.qualifier.trim() {
+            "" => qualifier,
+            code => code.parse()?,
+        };
+
+        Ok((entity, qualifier))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(toml: &str) -> DealerRules {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn buyer(name: &str, id: &str, entity: &str, qualifier: &str) -> EndBuyerData {
+        EndBuyerData {
+            name: name.to_string(),
+            id: id.to_string(),
+            address: String::new(),
+            city: String::new(),
+            state: String::new(),
+            zipcode: String::new(),
+            change: String::new(),
+            start: String::new(),
+            end: String::new(),
+            entity: entity.to_string(),
+            qualifier: qualifier.to_string(),
+        }
+    }
+
+    fn classify(rules: &DealerRules, name: &str, id: &str) -> (DealerType, IdentifierType) {
+        rules.classifier().unwrap().classify(&buyer(name, id, "", "")).unwrap()
+    }
+
+    const RULES: &str = r#"
+        entity = "EB"
+        qualifier = "92"
+
+        [[rules]]
+        name = "ACME HOSPITAL"
+        id_pattern = "^99"
+        entity = "DS"
+        qualifier = "11"
+
+        [[rules]]
+        name = "Acme Hospital"
+        entity = "BG"
+        qualifier = "21"
+
+        [[rules]]
+        id_pattern = "^99"
+        entity = "MF"
+        qualifier = "UL"
+    "#;
+
+    #[test]
+    fn the_first_matching_rule_wins() {
+        let rules = rules(RULES);
+
+        assert_eq!(classify(&rules, "ACME HOSPITAL", "9988"), (DealerType::Distributor, IdentifierType::Dea));
+        assert_eq!(classify(&rules, "acme hospital", "1234"), (DealerType::BuyingGroup, IdentifierType::Hin));
+    }
+
+    #[test]
+    fn an_id_pattern_matches_on_its_own() {
+        let rules = rules(RULES);
+
+        assert_eq!(classify(&rules, "GENERAL HOSPITAL", " 9901 "), (DealerType::Manufacturer, IdentifierType::Duns));
+        assert_eq!(classify(&rules, "GENERAL HOSPITAL", "1299"), (DealerType::EndUser, IdentifierType::VendorDefined));
+    }
+
+    #[test]
+    fn the_old_gpos_are_the_default_rules() {
+        let rules = DealerRules::default();
+
+        assert_eq!(classify(&rules, "Premier Hospitals", "1"), (DealerType::BuyingGroup, IdentifierType::Hin));
+        assert_eq!(classify(&rules, "GENERAL HOSPITAL", "1"), (DealerType::EndUser, IdentifierType::VendorDefined));
+    }
+
+    #[test]
+    fn csv_columns_override_the_rules() {
+        let classifier = rules(RULES).classifier().unwrap();
+
+        assert_eq!(classifier.classify(&buyer("ACME HOSPITAL", "9988", "EB", "")).unwrap(), (DealerType::EndUser, IdentifierType::Dea));
+        assert_eq!(classifier.classify(&buyer("GENERAL HOSPITAL", "1", "", "UL")).unwrap(), (DealerType::EndUser, IdentifierType::Duns));
+        assert!(classifier.classify(&buyer("GENERAL HOSPITAL", "1", "XX", "")).is_err());
+    }
+
+    #[test]
+    fn a_rule_needs_a_name_or_an_id_pattern() {
+        let rules = rules(r#"
+            [[rules]]
+            entity = "DS"
+            qualifier = "11"
+        "#);
+
+        assert!(rules.classifier().is_err());
+    }
+
+    #[test]
+    fn a_bad_id_pattern_is_reported() {
+        let rules = rules(r#"
+            [[rules]]
+            id_pattern = "(99"
+            entity = "DS"
+            qualifier = "11"
+        "#);
+
+        assert!(rules.classifier().err().unwrap().to_string().contains("(99"));
+    }
+}
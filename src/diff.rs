@@ -0,0 +1,199 @@
+use crate::read_csv::{by_part, ContractData, EndBuyerData};
+
+// purpose keywords understood by the contract csv
+const ADD: &str = "add";
+const DELETE: &str = "delete";
+const CHANGE: &str = "change";
+
+// the rows a change document needs, computed from two full snapshots
+pub struct ContractDiff {
+    pub agreements: Vec<ContractData>,
+    pub buyers: Vec<EndBuyerData>,
+    pub summary: Vec<String>,
+}
+
+fn describe_changes(previous: &ContractData, current: &ContractData) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if previous.price != current.price {
+        changes.push(format!("price {} -> {}", previous.price, current.price));
+    }
+    if previous.description != current.description {
+        changes.push(format!("description '{}' -> '{}'", previous.description, current.description));
+    }
+    if previous.start != current.start {
+        changes.push(format!("start {} -> {}", previous.start, current.start));
+    }
+    if previous.end != current.end {
+        changes.push(format!("end {} -> {}", previous.end, current.end));
+    }
+    if previous.uom != current.uom || previous.quantity != current.quantity {
+        changes.push(format!("pack {}/{:?} -> {}/{:?}", previous.uom, previous.quantity, current.uom, current.quantity));
+    }
+
+    changes
+}
+
+// lines are matched on part number: new parts are added (AI), missing parts
+// deleted (DI) and parts whose price, description or dates moved are changed (CI).
+// a part on several rows is one line, so all of its tiers are sent together
+fn diff_agreements(previous: &[ContractData], current: &[ContractData], summary: &mut Vec<String>) -> Vec<ContractData> {
+    let mut agreements = Vec::new();
+    let previous_parts = by_part(previous);
+    let current_parts = by_part(current);
+
+    for rows in current_parts.iter() {
+        let part = rows[0].part.as_str();
+
+        match previous_parts.iter().find(|p| p[0].part == part) {
+            None => {
+                for row in rows.iter() {
+                    summary.push(format!("+ line {} {} @ {}", row.part, row.description, row.price));
+                }
+                agreements.extend(rows.iter().map(|row| ContractData { purpose: ADD.to_string(), ..(*row).clone() }));
+            },
+            Some(prior) => {
+                let changes = match prior.len() == rows.len() {
+                    true => prior.iter().zip(rows.iter()).flat_map(|(p, c)| describe_changes(p, c)).collect(),
+                    false => vec![format!("{} -> {} price tiers", prior.len(), rows.len())],
+                };
+                if !changes.is_empty() {
+                    summary.push(format!("~ line {}: {}", part, changes.join(", ")));
+                    agreements.extend(rows.iter().map(|row| ContractData { purpose: CHANGE.to_string(), ..(*row).clone() }));
+                }
+            },
+        }
+    }
+
+    for rows in previous_parts.iter().filter(|p| !current_parts.iter().any(|c| c[0].part == p[0].part)) {
+        summary.push(format!("- line {} {}", rows[0].part, rows[0].description));
+        agreements.extend(rows.iter().map(|row| ContractData { purpose: DELETE.to_string(), ..(*row).clone() }));
+    }
+
+    agreements
+}
+
+// buyers are matched on id; a removed buyer is sent with its agreement expiring on `today`
+fn diff_buyers(previous: &[EndBuyerData], current: &[EndBuyerData], today: &str, summary: &mut Vec<String>) -> Vec<EndBuyerData> {
+    let mut buyers = Vec::new();
+
+    for buyer in current.iter().filter(|c| !previous.iter().any(|p| p.id == c.id)) {
+        summary.push(format!("+ buyer {} {}", buyer.id, buyer.name));
+        buyers.push(buyer.clone());
+    }
+
+    for buyer in previous.iter().filter(|p| !current.iter().any(|c| c.id == p.id)) {
+        summary.push(format!("- buyer {} {} (expires {})", buyer.id, buyer.name, today));
+        buyers.push(EndBuyerData { end: today.to_string(), ..buyer.clone() });
+    }
+
+    buyers
+}
+
+// `today` is the run date written in one of the accepted input formats, as the rows it ends up in are read again
+pub fn diff_snapshots(previous_contract: &[ContractData], current_contract: &[ContractData], previous_buyers: &[EndBuyerData], current_buyers: &[EndBuyerData], today: &str) -> ContractDiff {
+    let mut summary = Vec::new();
+
+    let agreements = diff_agreements(previous_contract, current_contract, &mut summary);
+    let buyers = diff_buyers(previous_buyers, current_buyers, today, &mut summary);
+
+    ContractDiff {
+        agreements,
+        buyers,
+        summary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    fn row(part: &str, price: i64, start: &str, end: &str) -> ContractData {
+        ContractData {
+            part: part.to_string(),
+            price: Decimal::new(price, 2),
+            description: format!("PART {}", part),
+            start: start.to_string(),
+            end: end.to_string(),
+            purpose: ADD.to_string(),
+            uom: String::new(),
+            quantity: None,
+        }
+    }
+
+    fn buyer(id: &str) -> EndBuyerData {
+        EndBuyerData {
+            name: format!("BUYER {}", id),
+            id: id.to_string(),
+            address: String::new(),
+            city: String::new(),
+            state: String::new(),
+            zipcode: String::new(),
+            change: String::new(),
+            start: String::new(),
+            end: String::new(),
+            entity: String::new(),
+            qualifier: String::new(),
+        }
+    }
+
+    fn purposes(diff: &ContractDiff) -> Vec<(&str, &str)> {
+        diff.agreements.iter().map(|row| (row.part.as_str(), row.purpose.as_str())).collect()
+    }
+
+    #[test]
+    fn identical_snapshots_carry_nothing() {
+        let rows = vec![row("775", 1200, "20230101", ""), row("776", 500, "20230101", "")];
+        let buyers = vec![buyer("1")];
+        let diff = diff_snapshots(&rows, &rows, &buyers, &buyers, "20230601");
+
+        assert!(diff.agreements.is_empty() && diff.buyers.is_empty() && diff.summary.is_empty());
+    }
+
+    #[test]
+    fn parts_are_added_changed_and_deleted() {
+        let previous = vec![row("775", 1200, "20230101", ""), row("776", 500, "20230101", ""), row("777", 300, "20230101", "")];
+        let current = vec![row("775", 1300, "20230101", ""), row("776", 500, "20230101", ""), row("778", 100, "20230101", "")];
+        let diff = diff_snapshots(&previous, &current, &[], &[], "20230601");
+
+        assert_eq!(purposes(&diff), vec![("775", CHANGE), ("778", ADD), ("777", DELETE)]);
+        assert_eq!(diff.summary, vec![
+            "~ line 775: price 12.00 -> 13.00",
+            "+ line 778 PART 778 @ 1.00",
+            "- line 777 PART 777",
+        ]);
+    }
+
+    #[test]
+    fn a_moved_tier_sends_every_tier_of_its_line() {
+        let previous = vec![row("775", 1200, "20230101", "20230630"), row("775", 1300, "20230701", "")];
+        let current = vec![row("775", 1200, "20230101", "20230731"), row("775", 1300, "20230801", "")];
+        let diff = diff_snapshots(&previous, &current, &[], &[], "20230601");
+
+        assert_eq!(purposes(&diff), vec![("775", CHANGE), ("775", CHANGE)]);
+        assert_eq!(diff.summary, vec!["~ line 775: end 20230630 -> 20230731, start 20230701 -> 20230801"]);
+    }
+
+    #[test]
+    fn a_new_tier_changes_the_line() {
+        let previous = vec![row("775", 1200, "20230101", "")];
+        let current = vec![row("775", 1200, "20230101", "20230630"), row("775", 1300, "20230701", "")];
+        let diff = diff_snapshots(&previous, &current, &[], &[], "20230601");
+
+        assert_eq!(diff.agreements.len(), 2);
+        assert_eq!(diff.summary, vec!["~ line 775: 1 -> 2 price tiers"]);
+    }
+
+    #[test]
+    fn removed_buyers_expire_today() {
+        let previous = vec![buyer("1"), buyer("2")];
+        let current = vec![buyer("2"), buyer("3")];
+        let diff = diff_snapshots(&[], &[], &previous, &current, "20230601");
+
+        let buyers: Vec<(&str, &str)> = diff.buyers.iter().map(|b| (b.id.as_str(), b.end.as_str())).collect();
+        assert_eq!(buyers, vec![("3", ""), ("1", "20230601")]);
+        assert_eq!(diff.summary, vec!["+ buyer 3 BUYER 3", "- buyer 1 BUYER 1 (expires 20230601)"]);
+    }
+}
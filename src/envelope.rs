@@ -0,0 +1,323 @@
+use chrono::{Local, NaiveDateTime};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::x12::{Delimiters, Segment};
+
+// price catalog (845) functional group
+const FUNCTIONAL_IDENTIFIER: &str = "PC";
+const NO_AUTHORIZATION: &str = "00";
+const NO_SECURITY: &str = "00";
+const EMPTY_INFORMATION: &str = "          ";
+const X12_AGENCY: &str = "X";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    #[serde(rename = "004010")]
+    V4010,
+    #[serde(rename = "005010")]
+    V5010,
+}
+
+impl Version {
+    // GS08 version / release / industry identifier
+    pub fn group_version(&self) -> &str {
+        match self {
+            Version::V4010 => "004010",
+            Version::V5010 => "005010",
+        }
+    }
+
+    // ISA12 interchange control version number
+    pub fn interchange_version(&self) -> &str {
+        match self {
+            Version::V4010 => "00401",
+            Version::V5010 => "00501",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageIndicator {
+    #[serde(rename = "P")]
+    Production,
+    #[serde(rename = "T")]
+    Test,
+}
+
+impl UsageIndicator {
+    pub fn code(&self) -> &str {
+        match self {
+            UsageIndicator::Production => "P",
+            UsageIndicator::Test => "T",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Envelope {
+    pub sender_qualifier: String,
+    pub sender_id: String,
+    pub receiver_qualifier: String,
+    pub receiver_id: String,
+    #[serde(default = "default_usage_indicator")]
+    pub usage_indicator: UsageIndicator,
+    #[serde(default)]
+    pub acknowledgment_requested: bool,
+    pub application_sender: String,
+    pub application_receiver: String,
+    #[serde(default = "default_version")]
+    pub version: Version,
+    #[serde(default = "default_element_separator")]
+    pub element_separator: char,
+    #[serde(default = "default_sub_element_separator")]
+    pub sub_element_separator: char,
+    #[serde(default = "default_repetition_separator")]
+    pub repetition_separator: char,
+    #[serde(default = "default_segment_terminator")]
+    pub segment_terminator: char,
+}
+
+fn default_usage_indicator() -> UsageIndicator {
+    UsageIndicator::Production
+}
+
+fn default_version() -> Version {
+    Version::V4010
+}
+
+fn default_element_separator() -> char {
+    '*'
+}
+
+fn default_sub_element_separator() -> char {
+    '>'
+}
+
+fn default_repetition_separator() -> char {
+    '^'
+}
+
+fn default_segment_terminator() -> char {
+    '~'
+}
+
+// ISA05 / ISA07 qualifiers are exactly 2 characters, ISA06 / ISA08 ids at most 15 (GS02 / GS03 2 to 15)
+const QUALIFIER_LENGTH: usize = 2;
+const ID_LENGTH: usize = 15;
+const APPLICATION_CODE_LENGTH: std::ops::RangeInclusive<usize> = 2..=15;
+
+// ISA elements are fixed width, so ids are space padded
+fn pad(value: &str, width: usize) -> String {
+    format!("{:<width$}", value, width = width)
+}
+
+impl Envelope {
+    // the key that is wrong and why, checked when the config loads since a bad
+    // value would otherwise shift the fixed width ISA without any error
+    pub fn check(&self) -> Result<(), (&'static str, String)> {
+        let separators = [
+            ("element_separator", self.element_separator),
+            ("sub_element_separator", self.sub_element_separator),
+            ("repetition_separator", self.repetition_separator),
+            ("segment_terminator", self.segment_terminator),
+        ];
+
+        for (idx, (key, separator)) in separators.iter().enumerate() {
+            if separator.is_alphanumeric() || *separator == ' ' {
+                return Err((key, format!("'{}' cannot be a delimiter, it may appear in the data", separator)));
+            }
+            if let Some((other, _)) = separators[..idx].iter().find(|(_, other)| other == separator) {
+                return Err((key, format!("'{}' is already the {}", separator, other)));
+            }
+        }
+
+        let delimiters = self.delimiters();
+        let values = [
+            ("sender_qualifier", &self.sender_qualifier, QUALIFIER_LENGTH..=QUALIFIER_LENGTH),
+            ("receiver_qualifier", &self.receiver_qualifier, QUALIFIER_LENGTH..=QUALIFIER_LENGTH),
+            ("sender_id", &self.sender_id, 1..=ID_LENGTH),
+            ("receiver_id", &self.receiver_id, 1..=ID_LENGTH),
+            ("application_sender", &self.application_sender, APPLICATION_CODE_LENGTH),
+            ("application_receiver", &self.application_receiver, APPLICATION_CODE_LENGTH),
+        ];
+
+        for (key, value, length) in values {
+            let chars = value.chars().count();
+            if !length.contains(&chars) {
+                let expected = match length.start() == length.end() {
+                    true => format!("{}", length.start()),
+                    false => format!("{} to {}", length.start(), length.end()),
+                };
+                return Err((key, format!("'{}' is {} characters, expected {}", value, chars, expected)));
+            }
+            if let Some(delimiter) = delimiters.find_in(value) {
+                return Err((key, format!("'{}' contains the delimiter '{}'", value, delimiter)));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn delimiters(&self) -> Delimiters {
+        Delimiters {
+            element: self.element_separator,
+            sub_element: self.sub_element_separator,
+            segment: self.segment_terminator,
+            repetition: match self.version {
+                Version::V4010 => None,
+                Version::V5010 => Some(self.repetition_separator),
+            },
+        }
+    }
+
+    pub fn interchange_header(&self, control_number: &str, created: &NaiveDateTime) -> Segment {
+        // ISA11 is the repetition separator from 005010 on, "U" (US EDI community) before that
+        let standards = match self.version {
+            Version::V4010 => "U".to_string(),
+            Version::V5010 => self.repetition_separator.to_string(),
+        };
+
+        Segment::new("ISA")
+            .element(NO_AUTHORIZATION)
+            .element(EMPTY_INFORMATION)
+            .element(NO_SECURITY)
+            .element(EMPTY_INFORMATION)
+            .element(&self.sender_qualifier)
+            .element(&pad(&self.sender_id, 15))
+            .element(&self.receiver_qualifier)
+            .element(&pad(&self.receiver_id, 15))
+            .element(&created.format("%y%m%d").to_string())
+            .element(&created.format("%H%M").to_string())
+            .element(&standards)
+            .element(self.version.interchange_version())
+            .element(&format!("{:0>9}", control_number))
+            .element(if self.acknowledgment_requested { "1" } else { "0" })
+            .element(self.usage_indicator.code())
+            .element(&self.sub_element_separator.to_string())
+    }
+
+    pub fn group_header(&self, control_number: &str, created: &NaiveDateTime) -> Segment {
+        Segment::new("GS")
+            .element(FUNCTIONAL_IDENTIFIER)
+            .element(&self.application_sender)
+            .element(&self.application_receiver)
+            .element(&created.format("%Y%m%d").to_string())
+            .element(&created.format("%H%M").to_string())
+            .element(control_number)
+            .element(X12_AGENCY)
+            .element(self.version.group_version())
+    }
+
+    // wraps the transaction sets in a single functional group and interchange
+    pub fn wrap(&self, transaction_sets: Vec<Vec<Segment>>, interchange_control_number: &str, group_control_number: &str) -> Vec<Segment> {
+        let created = Local::now().naive_local();
+        let set_count = transaction_sets.len();
+
+        let mut segments = vec![
+            self.interchange_header(interchange_control_number, &created),
+            self.group_header(group_control_number, &created),
+        ];
+
+        segments.extend(transaction_sets.into_iter().flatten());
+
+        segments.push(Segment::new("GE").element(&set_count.to_string()).element(group_control_number));
+        segments.push(Segment::new("IEA").element("1").element(&format!("{:0>9}", interchange_control_number)));
+
+        segments
+    }
+
+    // the same envelope in the shape the stedi translate api expects
+    pub fn to_stedi_json(&self, interchange_control_number: &str, group_control_number: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let created = Local::now().naive_local();
+
+        let envelope = json!({
+            "interchangeHeader": {
+                "senderQualifier": self.sender_qualifier,
+                "senderId": self.sender_id,
+                "receiverQualifier": self.receiver_qualifier,
+                "receiverId": self.receiver_id,
+                "date": created.format("%Y-%m-%d").to_string(),
+                "time": created.format("%H:%M").to_string(),
+                "controlNumber": interchange_control_number,
+                "usageIndicatorCode": self.usage_indicator.code(),
+            },
+            "groupHeader": {
+                "functionalIdentifierCode": FUNCTIONAL_IDENTIFIER,
+                "applicationSenderCode": self.application_sender,
+                "applicationReceiverCode": self.application_receiver,
+                "date": created.format("%Y-%m-%d").to_string(),
+                "time": created.format("%H:%M").to_string(),
+                "controlNumber": group_control_number,
+            },
+        });
+
+        Ok(serde_json::to_string(&envelope)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `overrides` replaces keys of a valid envelope
+    fn envelope(overrides: &str) -> Envelope {
+        let mut table: toml::Table = toml::from_str(r#"
+            sender_qualifier = "ZZ"
+            sender_id = "BUSSE"
+            receiver_qualifier = "01"
+            receiver_id = "123456789"
+            application_sender = "BUSSE"
+            application_receiver = "PARTNER"
+        "#).unwrap();
+        table.extend(toml::from_str::<toml::Table>(overrides).unwrap());
+
+        toml::Value::Table(table).try_into().unwrap()
+    }
+
+    fn key(extra: &str) -> Option<&'static str> {
+        envelope(extra).check().err().map(|(key, _)| key)
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(key(""), None);
+        assert_eq!(key("receiver_id = \"123456789012345\""), None);
+    }
+
+    #[test]
+    fn ids_and_qualifiers_must_fit_the_isa() {
+        assert_eq!(key("receiver_id = \"1234567890123456\""), Some("receiver_id"));
+        assert_eq!(key("sender_id = \"\""), Some("sender_id"));
+        assert_eq!(key("sender_qualifier = \"Z\""), Some("sender_qualifier"));
+        assert_eq!(key("application_receiver = \"P\""), Some("application_receiver"));
+        assert_eq!(key("sender_id = \"BUS*SE\""), Some("sender_id"));
+    }
+
+    #[test]
+    fn delimiters_must_be_distinct_and_not_data() {
+        assert_eq!(key("element_separator = \"A\""), Some("element_separator"));
+        assert_eq!(key("segment_terminator = \" \""), Some("segment_terminator"));
+        assert_eq!(key("sub_element_separator = \"*\""), Some("sub_element_separator"));
+
+        let (_, message) = envelope("segment_terminator = \"^\"").check().unwrap_err();
+        assert_eq!(message, "'^' is already the repetition_separator");
+    }
+
+    #[test]
+    fn the_repetition_separator_only_counts_from_005010() {
+        assert_eq!(key("sender_id = \"BUS^SE\""), None);
+        assert_eq!(key("version = \"005010\"\nsender_id = \"BUS^SE\""), Some("sender_id"));
+        assert_eq!(envelope("version = \"005010\"").delimiters().repetition, Some('^'));
+    }
+
+    #[test]
+    fn ids_are_padded_to_the_isa_width() {
+        let isa = envelope("").interchange_header("1", &chrono::NaiveDate::from_ymd_opt(2023, 1, 1).unwrap().and_hms_opt(9, 0, 0).unwrap())
+            .encode(&Delimiters::default())
+            .unwrap();
+
+        assert_eq!(isa.len(), 106);
+        assert!(isa.starts_with("ISA*00*          *00*          *ZZ*BUSSE          *01*123456789      *230101*0900*U*00401*000000001*0*P*>~"));
+    }
+}
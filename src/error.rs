@@ -0,0 +1,110 @@
+use std::fmt;
+use std::process::ExitCode;
+
+use crate::config::ConfigError;
+use crate::stedi::StediError;
+
+// exit codes, one per kind of failure; 1 is left for anything unexpected and
+// 2 is what clap uses for bad arguments
+const EXIT_VALIDATION: u8 = 3;
+const EXIT_CONFIG: u8 = 4;
+const EXIT_INPUT: u8 = 5;
+const EXIT_TRANSLATION: u8 = 6;
+const EXIT_TRANSPORT: u8 = 7;
+const EXIT_OUTPUT: u8 = 8;
+// not an error: the run worked, but the partner's acknowledgment rejected what we sent
+pub const EXIT_REJECTED: u8 = 9;
+
+type Source = Box<dyn std::error::Error>;
+
+// every way a run can fail, grouped by what has to be done about it so a
+// scheduled job can tell bad input apart from stedi being down
+#[derive(Debug)]
+pub enum Error {
+    // config.toml is missing or wrong, including credentials and endpoints
+    Config(Source),
+    // an input file or argument could not be read
+    Input(Source),
+    // the inputs were read but break a rule; nothing was sent
+    Validation(String),
+    // the payload could not be turned into X12
+    Translation(Source),
+    // stedi could not be reached, or kept failing after the retries
+    Transport(StediError),
+    // the run directory, control numbers or history could not be written
+    Output(Source),
+}
+
+impl Error {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Config(_) => "config",
+            Error::Input(_) => "input",
+            Error::Validation(_) => "validation",
+            Error::Translation(_) => "translation",
+            Error::Transport(_) => "transport",
+            Error::Output(_) => "output",
+        }
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Error::Config(_) => EXIT_CONFIG,
+            Error::Input(_) => EXIT_INPUT,
+            Error::Validation(_) => EXIT_VALIDATION,
+            Error::Translation(_) => EXIT_TRANSLATION,
+            Error::Transport(_) => EXIT_TRANSPORT,
+            Error::Output(_) => EXIT_OUTPUT,
+        })
+    }
+
+    // translators report through Box<dyn Error>; stedi failures are sorted by
+    // their cause, anything else is a translation failure
+    pub fn translation(source: Source) -> Self {
+        match source.downcast::<StediError>() {
+            Ok(stedi) => Error::from(*stedi),
+            Err(source) => Error::Translation(source),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(source) | Error::Input(source) | Error::Translation(source) | Error::Output(source) => write!(f, "{}", source),
+            Error::Validation(message) => write!(f, "{}", message),
+            Error::Transport(source) => write!(f, "{}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(Box::new(e))
+    }
+}
+
+impl From<StediError> for Error {
+    fn from(e: StediError) -> Self {
+        match e {
+            StediError::Transport(_) => Error::Transport(e),
+            StediError::Status { status, .. } if status == 429 || status >= 500 => Error::Transport(e),
+            StediError::Unauthorized(_) | StediError::InvalidUrl(_) => Error::Config(Box::new(e)),
+            StediError::Input(_) => Error::Input(Box::new(e)),
+            StediError::Status { .. } | StediError::InvalidResponse(_) | StediError::MissingOutput(_) | StediError::InvalidHeader(_) => Error::Translation(Box::new(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_rejected_ack_does_not_share_the_validation_code() {
+        assert_ne!(EXIT_REJECTED, EXIT_VALIDATION);
+        assert_eq!(Error::Validation("bad csv".to_string()).exit_code(), ExitCode::from(EXIT_VALIDATION));
+    }
+}
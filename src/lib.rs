@@ -0,0 +1,39 @@
+//! Builds X12 845 price authorization documents for Busse contracts.
+//!
+//! The `busse-845-edi-v2` binary is a thin command line over this crate. Other
+//! services can build documents directly with [`ContractBuilder`], or from the
+//! same csv files the command line reads with [`build::DocumentBuilder`], then
+//! turn a [`payload::Payload`] into X12 with [`x12::encode`] or a
+//! [`translate::Translator`].
+
+pub mod ack;
+pub mod archive;
+pub mod build;
+pub mod config;
+pub mod contract;
+pub mod date;
+pub mod control_number;
+pub mod dealer_rules;
+pub mod diff;
+pub mod envelope;
+pub mod error;
+pub mod manifest;
+pub mod payload;
+pub mod preview;
+pub mod price;
+pub mod purpose;
+pub mod read_csv;
+pub mod read_x12;
+pub mod rules;
+pub mod secret;
+pub mod stedi;
+pub mod to_edi;
+pub mod to_json;
+pub mod translate;
+pub mod uom;
+pub mod validate;
+pub mod x12;
+
+pub use crate::contract::{Buyer, ContractBuilder, LineItem};
+pub use crate::error::Error;
+pub use crate::payload::{Document, DocumentType, Payload};
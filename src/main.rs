@@ -1,325 +1,481 @@
-mod config;
-mod control_number;
-mod payload;
-mod read_csv;
-mod to_json;
-mod stedi;
-mod to_edi;
-
-use chrono::NaiveDate;
-use clap::Parser;
-
-use crate::config::Config;
-use crate::payload::*;
-use crate::read_csv::*;
-use crate::to_json::*;
-use crate::stedi::*;
-use crate::to_edi::*;
-
-const CASE: &str = "CA";
-const ADD: &str = "AI";
-const CHANGE: &str = "CI";
-const DELETE: &str = "DI";
+use std::path::Path;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+use busse_845_edi_v2::ack::*;
+use busse_845_edi_v2::archive::*;
+use busse_845_edi_v2::build::*;
+use busse_845_edi_v2::config::Config;
+use busse_845_edi_v2::control_number::*;
+use busse_845_edi_v2::diff::*;
+use busse_845_edi_v2::error::*;
+use busse_845_edi_v2::payload::*;
+use busse_845_edi_v2::manifest::*;
+use busse_845_edi_v2::preview::*;
+use busse_845_edi_v2::read_csv::*;
+use busse_845_edi_v2::read_x12::*;
+use busse_845_edi_v2::rules::*;
+use busse_845_edi_v2::to_edi::*;
+use busse_845_edi_v2::to_json::*;
+use busse_845_edi_v2::translate::*;
+use busse_845_edi_v2::validate::*;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
-struct Args {
-    #[clap(short, long, value_parser)]
-    contract_number: String,    
-    #[clap(short, long, value_parser)]
+struct Cli {
+    /// config file to use instead of looking for config.toml
+    #[clap(long, value_parser, global = true)]
+    config: Option<String>,
+    /// trading partner profile from [partners.<name>] in config.toml
+    #[clap(long, value_parser, global = true)]
+    partner: Option<String>,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// check the contract and buyer csv files and report every problem
+    Validate {
+        #[clap(flatten)]
+        jobs: JobArgs,
+        /// also write the validation report to this file as json
+        #[clap(long, value_parser)]
+        validation_report: Option<String>,
+    },
+    /// build the payload and print it without numbering, archiving or sending anything
+    Build {
+        #[clap(flatten)]
+        jobs: JobArgs,
+        #[clap(long, value_enum, default_value = "table")]
+        format: ReportFormat,
+    },
+    /// build, translate and write the 845 into a new run directory
+    Send {
+        #[clap(flatten)]
+        jobs: JobArgs,
+        /// also write the validation report to this file as json
+        #[clap(long, value_parser)]
+        validation_report: Option<String>,
+        /// how the payload becomes X12 - overrides [translator] backend in config.toml
+        #[clap(long, value_enum)]
+        translator: Option<Backend>,
+        /// shorthand for --translator native
+        #[clap(long, value_parser, default_value_t = false, conflicts_with = "translator")]
+        native: bool,
+    },
+    /// show what a change document would carry between two snapshots
+    Diff {
+        /// current contract csv
+        #[clap(short, long, value_parser)]
+        contract_number: String,
+        /// previous snapshot of the contract csv
+        #[clap(long, value_parser)]
+        previous_contract_file: String,
+        /// current buyer csv
+        #[clap(short, long, value_parser)]
+        buyer_file: Option<String>,
+        /// previous snapshot of the buyer csv
+        #[clap(long, value_parser, requires = "buyer_file")]
+        previous_buyer_file: Option<String>,
+    },
+    /// read an existing 845 (e.g. an archived output.edi) and print it as payload json
+    Parse {
+        file: String,
+    },
+    /// list the transaction sets sent to the partner
+    History {
+        /// only this contract
+        #[clap(short, long, value_parser)]
+        contract_number: Option<String>,
+        /// only the most recent entries
+        #[clap(short, long, value_parser)]
+        limit: Option<usize>,
+    },
+    /// read a 997 / 999 and match it against the history
+    Ack {
+        file: String,
+    },
+}
+
+// one contract from the command line, or every contract in a manifest
+#[derive(clap::Args, Debug)]
+struct JobArgs {
+    #[clap(short, long, value_parser, required_unless_present = "manifest", default_value = "")]
+    contract_number: String,
+    #[clap(short, long, value_parser, required_unless_present = "manifest", default_value = "")]
     buyer_file: String,
-    #[clap(short, long, value_parser)]
+    #[clap(short, long, value_parser, required_unless_present = "manifest", default_value = "")]
     start_date: String,
-    #[clap(short, long, value_parser)]
+    #[clap(short, long, value_parser, required_unless_present = "manifest", default_value = "")]
     end_date: String,
-    #[clap(short, long, value_parser)]
-    purpose: String,
-    
+    #[clap(short, long, value_enum, required_unless_present = "manifest")]
+    purpose: Option<DocumentType>,
+
     #[clap(short, long, value_parser, default_value = "")]
     new_end_date_if_any: String,
     #[clap(short, long, value_parser, default_value = "")]
     outgoing_contract_number_if_any: String,
+
+    /// previous snapshot of the contract csv - a change document is computed from the differences
+    #[clap(long, value_parser, default_value = "")]
+    previous_contract_file: String,
+    /// previous snapshot of the buyer csv, used with --previous-contract-file
+    #[clap(long, value_parser, default_value = "")]
+    previous_buyer_file: String,
+
+    /// build every contract listed in this manifest (.toml or .csv) into one interchange
+    #[clap(long, value_parser)]
+    manifest: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // println!("{:?}", cli);
+
+    match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error ({}): {}", e.kind(), e);
+            e.exit_code()
+        },
+    }
 }
 
+fn run(cli: Cli) -> Result<ExitCode, Error> {
+    // parsing an 845 needs no config
+    if let Command::Parse { file } = &cli.command {
+        let edi = std::fs::read_to_string(file).map_err(|e| Error::Input(format!("Unable to read {}: {}", file, e).into()))?;
+        let payload = parse_x12(edi.as_str()).map_err(Error::Input)?;
+        println!("{}", serde_json::to_string_pretty(&payload).map_err(|e| Error::Output(e.into()))?);
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::new();
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let config = Config::load(cli.config.as_deref().map(Path::new), cli.partner.as_deref())?;
     // println!("{:?}", config);
 
-    let args: Args = Args::parse();
-    // println!("{:?}", args);
+    match &cli.command {
+        Command::Validate { jobs, validation_report } => validate(&config, jobs, validation_report.as_deref()),
+        Command::Build { jobs, format } => {
+            let builder = DocumentBuilder::new(&config)?;
+            let (_, payload) = build_payload(&builder, &config.get_rules(), jobs, None)?;
 
-    let purpose = match args.purpose.as_str() {
-        "new" => DocumentType::New,
-        "change" => DocumentType::Change,
-        "resubmit" => DocumentType::Resubmit,
-        "renew" => DocumentType::Renew,
-        "cancel" => DocumentType::Cancel,
-        _ => DocumentType::New,
-    };    
+            print!("{}", render(&payload, *format).map_err(Error::Output)?);
 
-    let mut payload = Payload::new();    
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Send { jobs, validation_report, translator, native } => {
+            let backend = match (native, translator) {
+                (true, _) => Backend::Native,
+                (false, Some(backend)) => *backend,
+                (false, None) => config.get_backend(),
+            };
 
-    if (purpose.clone() == DocumentType::Cancel || purpose.clone() == DocumentType::Renew) && &args.new_end_date_if_any == "" {
-        panic!("New end date is required for cancel or renew purposes.");
+            send(&config, jobs, validation_report.as_deref(), backend)
+        },
+        Command::Diff { contract_number, previous_contract_file, buyer_file, previous_buyer_file } => {
+            let buyers = |file: &Option<String>| -> Result<Vec<EndBuyerData>, Error> {
+                match file {
+                    Some(file) => parse_csv::<EndBuyerData>(config.get_buyers_path(file).as_path()).map_err(Error::Input),
+                    None => Ok(Vec::new()),
+                }
+            };
+            let contracts = |file: &str| parse_csv::<ContractData>(config.get_contracts_path(file).as_path()).map_err(Error::Input);
+
+            let diff = diff_snapshots(
+                &contracts(previous_contract_file)?,
+                &contracts(contract_number)?,
+                // without a previous buyer file the current buyers are compared with themselves
+                &buyers(match previous_buyer_file {
+                    Some(_) => previous_buyer_file,
+                    None => buyer_file,
+                })?,
+                &buyers(buyer_file)?,
+                config.get_date_formats().format(chrono::Local::now().date_naive()).as_str(),
+            );
+
+            for line in diff.summary.iter() {
+                println!("{}", line);
+            }
+            if diff.summary.is_empty() {
+                println!("no differences");
+            }
+
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::History { contract_number, limit } => {
+            let registry = open_registry(&config)?;
+            let entries: Vec<HistoryEntry> = registry.history().map_err(Error::Output)?.into_iter()
+                .filter(|entry| contract_number.as_ref().is_none_or(|contract| &entry.contract == contract))
+                .collect();
+            let skip = limit.map_or(0, |limit| entries.len().saturating_sub(limit));
+
+            for entry in entries.iter().skip(skip) {
+                println!("{}  ISA {}  GS {}  ST {}  {} ({})  {}", entry.timestamp, entry.interchange, entry.group, entry.transaction_set, entry.contract, entry.purpose, entry.output_file);
+            }
+
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Ack { file } => ack(&config, file),
+        Command::Parse { .. } => unreachable!("parse is handled before the config is loaded"),
     }
+}
 
-    let mut document = Document::new(purpose.clone());
-    document.add_datetime(Datetime::new(DatetimeType::ContractEffective, &args.start_date));    
-    
-    if &args.new_end_date_if_any != "" {
-        document.add_datetime(Datetime::new(DatetimeType::ContractExpiration, &args.new_end_date_if_any));
-        document.add_datetime(Datetime::new(DatetimeType::ContractPriorExpiration, &args.end_date));
-    } else {
-        document.add_datetime(Datetime::new(DatetimeType::ContractExpiration, &args.end_date));
+fn job_from_args(args: &JobArgs) -> ContractJob {
+    ContractJob {
+        contract_number: args.contract_number.clone(),
+        buyer_file: args.buyer_file.clone(),
+        start_date: args.start_date.clone(),
+        end_date: args.end_date.clone(),
+        // clap requires it whenever there is no manifest
+        purpose: args.purpose.unwrap_or(DocumentType::New),
+        new_end_date_if_any: args.new_end_date_if_any.clone(),
+        outgoing_contract_number_if_any: args.outgoing_contract_number_if_any.clone(),
+        previous_contract_file: args.previous_contract_file.clone(),
+        previous_buyer_file: args.previous_buyer_file.clone(),
     }
+}
 
-    let mut contract = Contract::new(args.contract_number.as_str());        
+fn read_jobs(args: &JobArgs) -> Result<Vec<ContractJob>, Error> {
+    match args.manifest.as_deref() {
+        Some(manifest) => read_manifest(Path::new(manifest)).map_err(Error::Input),
+        None => Ok(vec![job_from_args(args)]),
+    }
+}
 
-    match purpose {
-        DocumentType::New => {
-            if &args.outgoing_contract_number_if_any != "" {
-                contract.add_reference(ReferenceType::MutuallyDefined, &args.outgoing_contract_number_if_any);
-            }
+// input files are validated before anything is built so all problems are reported at once,
+// then the business rules are checked on the built document
+fn build_job(builder: &DocumentBuilder, rules: &RuleSettings, job: &ContractJob, report: &mut ValidationReport) -> Result<Document, Error> {
+    let known_issues = report.issues.len();
+    builder.validate(job, report)?;
 
-            let buyer_data = parse_csv::<EndBuyerData>(config.get_buyers_path(args.buyer_file.as_str()).as_path())?;    
-
-            for buyer in buyer_data.into_iter() {
-                let mut processed = Dealer::new(match buyer.name.as_str() {
-                    "MEDASSETS" => DealerType::BuyingGroup,
-                    "PREMIER HOSPITALS" => DealerType::BuyingGroup,
-                    _ => DealerType::EndUser,
-                }, buyer.name.as_str(), match buyer.name.as_str() {
-                    "MEDASSETS" => IdentifierType::Hin,
-                    "PREMIER HOSPITALS" => IdentifierType::Hin,
-                    _ => IdentifierType::VendorDefined,
-                }, buyer.id.as_str());
-
-                if buyer.address != "" {
-                    processed.set_address(Address::new(buyer.address.as_str(), buyer.city.as_str(), buyer.state.as_str(), buyer.zipcode.as_str()));
-                }
+    if report.issues.len() > known_issues {
+        return Err(Error::Validation(format!("{} problem(s) found in the input files.", report.issues.len() - known_issues)));
+    }
 
-                processed.add_reference(ReferenceType::AddDistributor, &buyer.change);
+    // the diff summary goes to stderr so build --format json / markdown stays clean
+    let (document, summary) = builder.build(job)?;
+    if !summary.is_empty() {
+        eprintln!("{} change(s) from {} to {}:", summary.len(), job.previous_contract_file, job.contract_number);
+        for line in summary.iter() {
+            eprintln!("  {}", line);
+        }
+    }
 
-                if buyer.start != "" {            
-                    processed.add_datetime(Datetime::new(DatetimeType::AgreementEffective, match buyer.start.len() {
-                        8 => format!("{}-{}-{}", &buyer.start[0..4], &buyer.start[4..6], &buyer.start[6..8]),
-                        _ => buyer.start,
-                    }.as_str()));
-                }
+    let findings = check_document(&document, rules);
+    for finding in findings.iter() {
+        eprintln!("{}", finding);
+    }
 
-                if buyer.end != "" {            
-                    processed.add_datetime(Datetime::new(DatetimeType::AgreementExpiration, match buyer.end.len() {
-                        8 => format!("{}-{}-{}", &buyer.end[0..4], &buyer.end[4..6], &buyer.end[6..8]),
-                        _ => buyer.end,
-                    }.as_str()));
-                }
-                
-                contract.add_dealer(processed);
-            };
+    match findings.iter().filter(|finding| finding.severity == Severity::Error).count() {
+        0 => Ok(document),
+        n => Err(Error::Validation(format!("{} business rule error(s) in contract {}.", n, job.contract_number))),
+    }
+}
 
-            let contract_data = parse_csv::<ContractData>(config.get_contracts_path(args.contract_number.as_str()).as_path())?;    
-                
-            for (idx, agreement) in contract_data.iter().enumerate() {
-                let line_number: i32 = (idx + 1).try_into().unwrap();
-                
-                let mut processed = Agreement::new(&line_number, match agreement.purpose.as_str() {
-                    "add" => ADD,
-                    "delete" => DELETE,
-                    "change" => CHANGE,
-                    _ => ADD,
-                });
-                
-                processed.add_detail(agreement.description.as_str());
-                processed.add_line(&line_number, agreement.part.as_str());
-                processed.add_pricing(Pricing::new(agreement.price, 1, CASE, match agreement.start.len() {
-                    8 => format!("{}-{}-{}", &agreement.start[0..4], &agreement.start[4..6], &agreement.start[6..8]),
-                    _ => agreement.start.clone(),            
-                }.as_str(), match args.end_date.len() {
-                    8 => format!("{}-{}-{}", &args.end_date[0..4], &args.end_date[4..6], &args.end_date[6..8]),
-                    _ => args.end_date.clone(),
-                }.as_str()));        
-
-                contract.add_agreement(processed);
-            }
+// the jobs that were read and the payload built from them
+type Built = (Vec<ContractJob>, Payload);
 
-            document.add_contract(contract);
-            payload.add_document(document);
-        },
-        // change type - the buyer and contract csv files should contain only changes
-        DocumentType::Change => {
-            let buyer_data = parse_csv::<EndBuyerData>(config.get_buyers_path(args.buyer_file.as_str()).as_path())?;
-
-            if buyer_data.len() > 0 {
-                for buyer in buyer_data.into_iter() {
-                    let mut processed = Dealer::new(match buyer.name.as_str() {
-                        "MEDASSETS" => DealerType::BuyingGroup,
-                        "PREMIER HOSPITALS" => DealerType::BuyingGroup,
-                        _ => DealerType::EndUser,
-                    }, buyer.name.as_str(), match buyer.name.as_str() {
-                        "MEDASSETS" => IdentifierType::Hin,
-                        "PREMIER HOSPITALS" => IdentifierType::Hin,
-                        _ => IdentifierType::VendorDefined,
-                    }, buyer.id.as_str());
-
-                    if buyer.address != "" {
-                        processed.set_address(Address::new(buyer.address.as_str(), buyer.city.as_str(), buyer.state.as_str(), buyer.zipcode.as_str()));
-                    }
-
-                    if buyer.start != "" {            
-                        processed.add_datetime(Datetime::new(DatetimeType::AgreementEffective, match buyer.start.len() {
-                            8 => format!("{}-{}-{}", &buyer.start[0..4], &buyer.start[4..6], &buyer.start[6..8]),
-                            _ => buyer.start,
-                        }.as_str()));
-                    }
-
-                    if buyer.end != "" {            
-                        processed.add_datetime(Datetime::new(DatetimeType::AgreementExpiration, match buyer.end.len() {
-                            8 => format!("{}-{}-{}", &buyer.end[0..4], &buyer.end[4..6], &buyer.end[6..8]),
-                            _ => buyer.end,
-                        }.as_str()));
-                    }
-                    
-                    contract.add_dealer(processed);
-                };
-            }
+// fails when any job failed - the problems have been printed and nothing may be sent
+fn build_payload(builder: &DocumentBuilder, rules: &RuleSettings, args: &JobArgs, validation_report: Option<&str>) -> Result<Built, Error> {
+    let jobs = read_jobs(args)?;
 
-            let contract_data = parse_csv::<ContractData>(config.get_contracts_path(args.contract_number.as_str()).as_path())?;    
-                
-            if contract_data.len() > 0 {
-                for (idx, agreement) in contract_data.iter().enumerate() {
-                    let line_number: i32 = (idx + 1).try_into().unwrap();
-                    
-                    let mut processed = Agreement::new(&line_number, match agreement.purpose.as_str() {
-                        "add" => ADD,
-                        "delete" => DELETE,
-                        "change" => CHANGE,
-                        _ => ADD,
-                    });
-                    
-                    processed.add_detail(agreement.description.as_str());
-                    processed.add_line(&line_number, agreement.part.as_str());
-                    processed.add_pricing(Pricing::new(agreement.price, 1, CASE, match agreement.start.len() {
-                        8 => format!("{}-{}-{}", &agreement.start[0..4], &agreement.start[4..6], &agreement.start[6..8]),
-                        _ => agreement.start.clone(),            
-                    }.as_str(), match args.end_date.len() {
-                        8 => format!("{}-{}-{}", &args.end_date[0..4], &args.end_date[4..6], &args.end_date[6..8]),
-                        _ => args.end_date.clone(),
-                    }.as_str()));        
-
-                    contract.add_agreement(processed);
-                }                                
-            }
+    let mut report = ValidationReport::new();
+    let mut payload = Payload::new();
+    // one outcome per job, in manifest order
+    let mut outcomes: Vec<Result<(), Error>> = Vec::new();
 
-            document.add_contract(contract);
-            payload.add_document(document);
-        },
-        DocumentType::Resubmit => {
-            if &args.outgoing_contract_number_if_any != "" {
-                contract.add_reference(ReferenceType::MutuallyDefined, &args.outgoing_contract_number_if_any);
+    for job in jobs.iter() {
+        outcomes.push(build_job(builder, rules, job, &mut report).map(|document| payload.add_document(document)));
+    }
+
+    if let Some(report_file) = validation_report {
+        report.write_json(Path::new(report_file)).map_err(Error::Output)?;
+    }
+
+    if !report.is_empty() {
+        report.print();
+    }
+
+    if args.manifest.is_some() {
+        for (job, outcome) in jobs.iter().zip(outcomes.iter()) {
+            match outcome {
+                Ok(()) => eprintln!("PASS {} ({})", job.contract_number, job.purpose.name()),
+                Err(reason) => eprintln!("FAIL {} ({}) - {}", job.contract_number, job.purpose.name(), reason),
             }
+        }
+    }
 
-            let buyer_data = parse_csv::<EndBuyerData>(config.get_buyers_path(args.buyer_file.as_str()).as_path())?;    
-
-            for buyer in buyer_data.into_iter() {
-                let mut processed = Dealer::new(match buyer.name.as_str() {
-                    "MEDASSETS" => DealerType::BuyingGroup,
-                    "PREMIER HOSPITALS" => DealerType::BuyingGroup,
-                    _ => DealerType::EndUser,
-                }, buyer.name.as_str(), match buyer.name.as_str() {
-                    "MEDASSETS" => IdentifierType::Hin,
-                    "PREMIER HOSPITALS" => IdentifierType::Hin,
-                    _ => IdentifierType::VendorDefined,
-                }, buyer.id.as_str());
-
-                if buyer.address != "" {
-                    processed.set_address(Address::new(buyer.address.as_str(), buyer.city.as_str(), buyer.state.as_str(), buyer.zipcode.as_str()));
-                }
+    // one bad contract holds back the whole interchange
+    let mut failures: Vec<Error> = outcomes.into_iter().filter_map(|outcome| outcome.err()).collect();
+    match failures.len() {
+        0 => Ok((jobs, payload)),
+        // a single contract fails with its own error, and exit code
+        1 if jobs.len() == 1 => Err(failures.remove(0)),
+        n => Err(Error::Validation(format!("{} of {} contract(s) failed, nothing was sent.", n, jobs.len()))),
+    }
+}
 
-                if buyer.start != "" {            
-                    processed.add_datetime(Datetime::new(DatetimeType::AgreementEffective, match buyer.start.len() {
-                        8 => format!("{}-{}-{}", &buyer.start[0..4], &buyer.start[4..6], &buyer.start[6..8]),
-                        _ => buyer.start,
-                    }.as_str()));
-                }
+// everything send checks - the csv files, then the business rules on the built documents
+fn validate(config: &Config, args: &JobArgs, validation_report: Option<&str>) -> Result<ExitCode, Error> {
+    let builder = DocumentBuilder::new(config)?;
+    build_payload(&builder, &config.get_rules(), args, validation_report)?;
 
-                if buyer.end != "" {            
-                    processed.add_datetime(Datetime::new(DatetimeType::AgreementExpiration, match buyer.end.len() {
-                        8 => format!("{}-{}-{}", &buyer.end[0..4], &buyer.end[4..6], &buyer.end[6..8]),
-                        _ => buyer.end,
-                    }.as_str()));
-                }
-                
-                contract.add_dealer(processed);
-            };
+    println!("no problems found");
 
-            let contract_data = parse_csv::<ContractData>(config.get_contracts_path(args.contract_number.as_str()).as_path())?;
-                
-            for (idx, agreement) in contract_data.iter().enumerate() {
-                let line_number: i32 = (idx + 1).try_into().unwrap();
-                
-                let mut processed = Agreement::new(&line_number, match agreement.purpose.as_str() {
-                    "add" => ADD,
-                    "delete" => DELETE,
-                    "change" => CHANGE,
-                    _ => ADD,
-                });
-                
-                processed.add_detail(agreement.description.as_str());
-                processed.add_line(&line_number, agreement.part.as_str());
-                processed.add_pricing(Pricing::new(agreement.price, 1, CASE, match agreement.start.len() {
-                    8 => format!("{}-{}-{}", &agreement.start[0..4], &agreement.start[4..6], &agreement.start[6..8]),
-                    _ => agreement.start.clone(),            
-                }.as_str(), match args.end_date.len() {
-                    8 => format!("{}-{}-{}", &args.end_date[0..4], &args.end_date[4..6], &args.end_date[6..8]),
-                    _ => args.end_date.clone(),
-                }.as_str()));        
-
-                contract.add_agreement(processed);
-            }    
-
-            document.add_contract(contract);
-            payload.add_document(document);
-        },
-        DocumentType::Renew => {
-            let end_date = NaiveDate::parse_from_str(args.end_date.as_str(), "%Y-%m-%d").unwrap();
-            let new_end_date = NaiveDate::parse_from_str(args.new_end_date_if_any.as_str(), "%Y-%m-%d").unwrap();
+    Ok(ExitCode::SUCCESS)
+}
 
-            if end_date > new_end_date {
-                panic!("New end date must be greater than current end date");                
-            }
+// control numbers are kept per receiver so their duplicate detection keeps working across runs,
+// whichever profile reaches it; without an envelope (stedi.json) the profile name stands in
+fn open_registry(config: &Config) -> Result<ControlNumberRegistry, Error> {
+    let dir = config.get_control_numbers_path();
 
-            document.add_contract(contract);
-            payload.add_document(document);
+    let registry = match config.get_envelope() {
+        Some(envelope) => {
+            let registry = ControlNumberRegistry::open(dir.as_path(), format!("{}-{}", envelope.receiver_qualifier, envelope.receiver_id).as_str());
+            // sequences used to be kept under the receiver id alone
+            registry.and_then(|registry| registry.adopt(envelope.receiver_id.as_str()).map(|_| registry))
         },
-        DocumentType::Cancel => {
-            let end_date = NaiveDate::parse_from_str(args.end_date.as_str(), "%Y-%m-%d").unwrap();
-            let new_end_date = NaiveDate::parse_from_str(args.new_end_date_if_any.as_str(), "%Y-%m-%d").unwrap();
+        None => ControlNumberRegistry::open(dir.as_path(), config.get_partner().unwrap_or("default")),
+    };
 
-            if end_date < new_end_date {
-                panic!("New end date must be less than current end date");                
-            }
-            document.add_contract(contract);
-            payload.add_document(document);
-        },
-    }
+    registry.map_err(Error::Output)
+}
+
+fn send(config: &Config, args: &JobArgs, validation_report: Option<&str>, backend: Backend) -> Result<ExitCode, Error> {
+    let builder = DocumentBuilder::new(config)?;
+    let (jobs, mut payload) = build_payload(&builder, &config.get_rules(), args, validation_report)?;
 
     // println!("{:?}", payload);
 
+    let translator = translator(backend, config).map_err(Error::Config)?;
+    let registry = open_registry(config)?;
+
+    for document in payload.documents.iter_mut() {
+        document.set_control_number(registry.next(Sequence::TransactionSet).map_err(Error::Output)?);
+    }
+
+    // interchange (ISA13) and group (GS06) numbers are only ours to assign when we own the envelope
+    let (interchange_control_number, group_control_number) = match config.get_envelope() {
+        Some(_) => (
+            registry.next(Sequence::Interchange).map_err(Error::Output)?.to_string(),
+            registry.next(Sequence::Group).map_err(Error::Output)?.to_string(),
+        ),
+        None => (String::new(), String::new()),
+    };
+
+    let run = create_run_dir(config, args, &jobs, &payload, &interchange_control_number).map_err(Error::Output)?;
+
+    if let Some(manifest) = args.manifest.as_deref() {
+        run.archive_input("manifest", Path::new(manifest)).map_err(Error::Input)?;
+    }
+    for job in jobs.iter() {
+        for (kind, path) in builder.input_files(job) {
+            run.archive_input(kind, path.as_path()).map_err(Error::Input)?;
+        }
+    }
+    for path in translator.inputs() {
+        run.archive_input(translator.name(), path.as_path()).map_err(Error::Input)?;
+    }
+
     // save payload to json file
-    to_json::<Payload>(&payload)?;
-    // combine payload with schema file
-    combine_schema_with_output_to_json()?;
+    to_json::<Payload>(&payload, run.file(JSON_OUTPUT_FILE).as_path()).map_err(Error::Output)?;
+
+    let outcome = translator.translate(&payload, &interchange_control_number, &group_control_number, &run).map_err(Error::translation);
+
+    if let Ok(Some(edi_string)) = &outcome {
+        // save edi_string to output.edi
+        write_to_file(edi_string, run.file(OUTPUT_FILE).as_path()).map_err(Error::Output)?;
+        record_history(&registry, &payload, &interchange_control_number, &group_control_number, run.file(OUTPUT_FILE).display().to_string().as_str()).map_err(Error::Output)?;
+    }
+
+    // the manifest is written whatever happened, so a failed run still leaves its trail
+    run.write_manifest(&RunSummary {
+        backend: translator.name().to_string(),
+        partner: registry.partner().to_string(),
+        interchange_control_number: interchange_control_number.clone(),
+        group_control_number: group_control_number.clone(),
+        documents: payload.documents.iter().map(|document| RunDocument {
+            control_number: document.control_number,
+            contract: document.contracts.iter().map(|contract| contract.contract_id.as_str()).collect::<Vec<&str>>().join(","),
+            purpose: document.document_type.clone(),
+        }).collect(),
+        sent: matches!(outcome, Ok(Some(_))),
+        error: outcome.as_ref().err().map(|e| format!("{} error: {}", e.kind(), e)),
+    }).map_err(Error::Output)?;
+
+    match outcome? {
+        Some(_) => println!("{}", run.file(OUTPUT_FILE).display()),
+        // nothing was sent, so there is nothing to log
+        None => println!("{} backend: payload written to {}", translator.name(), run.path().display()),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
 
-    // send post request to api to turn json into mapped_json
-    let (map_id, api_key, guide_id) = config.get_stedi_params();
-    let edi_string = make_api_call_to_stedi_for_edi_string(map_id, api_key, guide_id)?;
+// a single contract names the run after itself, a batch after its control number only
+fn create_run_dir(config: &Config, args: &JobArgs, jobs: &[ContractJob], payload: &Payload, interchange_control_number: &str) -> Result<RunDir, Box<dyn std::error::Error>> {
+    let control_number = match interchange_control_number {
+        "" => payload.documents.first().map_or(String::new(), |document| document.control_number.to_string()),
+        control_number => control_number.to_string(),
+    };
 
-    // dbg!(&edi_string);
+    match (args.manifest.as_deref(), jobs) {
+        (None, [job]) => RunDir::create(config.get_runs_path().as_path(), &[job.contract_number.as_str(), job.purpose.name(), control_number.as_str()]),
+        _ => RunDir::create(config.get_runs_path().as_path(), &["batch", control_number.as_str()]),
+    }
+}
 
-    // save edi_string to output.edi
-    write_to_file(&edi_string)?;
+fn record_history(registry: &ControlNumberRegistry, payload: &Payload, interchange_control_number: &str, group_control_number: &str, output_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    for document in payload.documents.iter() {
+        for contract in document.contracts.iter() {
+            registry.record(&HistoryEntry::new(
+                registry.partner(),
+                interchange_control_number,
+                group_control_number,
+                document.control_number,
+                contract.contract_id.as_str(),
+                document.document_type.as_str(),
+                output_file,
+            ))?;
+        }
+    }
 
     Ok(())
 }
 
+// each acknowledged transaction set is matched to what we sent by its group and ST control numbers
+fn ack(config: &Config, file: &str) -> Result<ExitCode, Error> {
+    let edi = std::fs::read_to_string(file).map_err(|e| Error::Input(format!("Unable to read {}: {}", file, e).into()))?;
+    let groups = parse_ack(edi.as_str()).map_err(Error::Input)?;
+    let history = open_registry(config)?.history().map_err(Error::Output)?;
+    let mut rejected = false;
+
+    for group in groups.iter() {
+        println!("GS {} ({}) - {}", group.control_number, group.functional_identifier, status_name(group.status.as_str()));
+        rejected |= is_rejected(group.status.as_str());
+
+        for transaction_set in group.transaction_sets.iter() {
+            let sent = history.iter().find(|entry| {
+                transaction_set.control_number.trim_start_matches('0') == entry.transaction_set.to_string()
+                    && (entry.group.is_empty() || entry.group == group.control_number)
+            });
+            let contract = sent.map_or("not in history".to_string(), |entry| format!("{} ({})", entry.contract, entry.purpose));
+
+            println!("  ST {} {} - {}", transaction_set.control_number, contract, status_name(transaction_set.status.as_str()));
+            for error in transaction_set.errors.iter() {
+                println!("    {}", error);
+            }
+            rejected |= is_rejected(transaction_set.status.as_str());
+        }
+    }
+
+    match rejected {
+        true => Ok(ExitCode::from(EXIT_REJECTED)),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
@@ -0,0 +1,24 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::build::ContractJob;
+use crate::read_csv::parse_csv;
+
+// a toml manifest is a list of [[contracts]] tables with the same keys as the csv columns
+#[derive(Deserialize)]
+struct Manifest {
+    contracts: Vec<ContractJob>,
+}
+
+// batch manifests list one contract per row (csv) or per [[contracts]] table (toml)
+pub fn read_manifest(path: &Path) -> Result<Vec<ContractJob>, Box<dyn std::error::Error>> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("toml") => {
+            let manifest: Manifest = toml::from_str(std::fs::read_to_string(path)?.as_str())?;
+            Ok(manifest.contracts)
+        },
+        Some("csv") => parse_csv::<ContractJob>(path),
+        _ => Err(format!("Manifest {} must be a .toml or .csv file.", path.display()).into()),
+    }
+}
@@ -1,12 +1,33 @@
-use crate::control_number::ControlNumber;
-use serde::{Serialize};
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::date::serialize_payload_date;
 
 // TODO: abstract components of the payload to their own files
 
 const MANUFACTURER: &str = "BUSSE HOSPITAL DISPOSABLES";
 const DUNS_NUMBER: &str = "0849233000001";
 
-#[derive(Debug, Serialize)]
+// the N1*MF party on every contract - [manufacturer] in config.toml or a partner profile
+#[derive(Deserialize, Debug, Clone)]
+pub struct Manufacturer {
+    pub name: String,
+    pub duns: String,
+}
+
+impl Default for Manufacturer {
+    fn default() -> Self {
+        Self {
+            name: MANUFACTURER.to_string(),
+            duns: DUNS_NUMBER.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct Payload {
     #[serde(rename = "default")]
     pub documents: Vec<Document>,
@@ -22,27 +43,45 @@ pub struct Document {
     pub contracts: Vec<Contract>,
 }
 
-#[derive(Clone, PartialEq, Eq, Debug)]
+// the purpose given on the command line (-p) or in a manifest row
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
 pub enum DocumentType {
     New,
     Change,
+    #[serde(alias = "reissue")]
+    #[value(alias = "reissue")]
     Resubmit,
     Renew,
     Cancel,
 }
 
 impl DocumentType {
-    pub fn to_string(&self) -> String {
+    pub fn name(&self) -> &str {
         match self {
-            DocumentType::New => "00".to_string(),
-            DocumentType::Change => "04".to_string(),
-            DocumentType::Resubmit => "18".to_string(),
-            DocumentType::Renew => "30".to_string(),
-            DocumentType::Cancel => "56".to_string(),
+            DocumentType::New => "new",
+            DocumentType::Change => "change",
+            DocumentType::Resubmit => "resubmit",
+            DocumentType::Renew => "renew",
+            DocumentType::Cancel => "cancel",
         }
     }
 }
 
+impl fmt::Display for DocumentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            DocumentType::New => "00",
+            DocumentType::Change => "04",
+            DocumentType::Resubmit => "18",
+            DocumentType::Renew => "30",
+            DocumentType::Cancel => "56",
+        };
+
+        write!(f, "{}", code)
+    }
+}
+
 pub enum DatetimeType {
     ContractEffective,
     ContractExpiration,
@@ -51,15 +90,17 @@ pub enum DatetimeType {
     AgreementExpiration,
 }
 
-impl DatetimeType {
-    pub fn to_string(&self) -> String {
-        match self {
-            DatetimeType::ContractEffective => "092".to_string(),
-            DatetimeType::ContractExpiration => "093".to_string(),
-            DatetimeType::ContractPriorExpiration => "094".to_string(),            
-            DatetimeType::AgreementEffective => "129".to_string(),
-            DatetimeType::AgreementExpiration => "130".to_string(),
-        }
+impl fmt::Display for DatetimeType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            DatetimeType::ContractEffective => "092",
+            DatetimeType::ContractExpiration => "093",
+            DatetimeType::ContractPriorExpiration => "094",            
+            DatetimeType::AgreementEffective => "129",
+            DatetimeType::AgreementExpiration => "130",
+        };
+
+        write!(f, "{}", code)
     }
 }
 
@@ -70,14 +111,37 @@ pub enum ReferenceType {
     AddDistributor,
 }
 
-impl ReferenceType {
-    pub fn to_string(&self) -> String {
-        match self {
-            ReferenceType::BuyersContractNumber => "BC".to_string(),
-            ReferenceType::ContractType => "CT".to_string(),
-            ReferenceType::MutuallyDefined => "ZZ".to_string(),
-            ReferenceType::AddDistributor => "TD".to_string(),
-        }
+impl fmt::Display for ReferenceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            ReferenceType::BuyersContractNumber => "BC",
+            ReferenceType::ContractType => "CT",
+            ReferenceType::MutuallyDefined => "ZZ",
+            ReferenceType::AddDistributor => "TD",
+        };
+
+        write!(f, "{}", code)
+    }
+}
+
+// what a line does to the contract, the agreement reference on the LIN loop
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LineChange {
+    #[default]
+    Add,
+    Change,
+    Delete,
+}
+
+impl fmt::Display for LineChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            LineChange::Add => "AI",
+            LineChange::Change => "CI",
+            LineChange::Delete => "DI",
+        };
+
+        write!(f, "{}", code)
     }
 }
 
@@ -101,35 +165,91 @@ pub struct Agreement {
     pub pricing: Vec<Pricing>,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(try_from = "String")]
 pub enum DealerType {
     Manufacturer,
     BuyingGroup,
     EndUser,
+    Distributor,
 }
 
 impl DealerType {
-    pub fn to_string(&self) -> String {
-        match self {
-            DealerType::Manufacturer => "MF".to_string(),
-            DealerType::BuyingGroup => "BG".to_string(),
-            DealerType::EndUser => "EB".to_string(),
-        }
+    pub const ALL: [DealerType; 4] = [DealerType::Manufacturer, DealerType::BuyingGroup, DealerType::EndUser, DealerType::Distributor];
+}
+
+impl fmt::Display for DealerType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            DealerType::Manufacturer => "MF",
+            DealerType::BuyingGroup => "BG",
+            DealerType::EndUser => "EB",
+            DealerType::Distributor => "DS",
+        };
+
+        write!(f, "{}", code)
     }
 }
 
+impl FromStr for DealerType {
+    type Err = String;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        DealerType::ALL.into_iter()
+            .find(|t| t.to_string() == code.trim().to_uppercase())
+            .ok_or_else(|| format!("'{}' is not a supported entity code", code))
+    }
+}
+
+impl TryFrom<String> for DealerType {
+    type Error = String;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        code.parse()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(try_from = "String")]
 pub enum IdentifierType {
     Duns,
     Hin,
     VendorDefined,
+    Dea,
 }
 
 impl IdentifierType {
-    pub fn to_string(&self) -> String {
-        match self {
-            IdentifierType::Duns => "UL".to_string(),
-            IdentifierType::Hin => "21".to_string(),
-            IdentifierType::VendorDefined => "92".to_string(),
-        }
+    pub const ALL: [IdentifierType; 4] = [IdentifierType::Duns, IdentifierType::Hin, IdentifierType::VendorDefined, IdentifierType::Dea];
+}
+
+impl fmt::Display for IdentifierType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            IdentifierType::Duns => "UL",
+            IdentifierType::Hin => "21",
+            IdentifierType::VendorDefined => "92",
+            IdentifierType::Dea => "11",
+        };
+
+        write!(f, "{}", code)
+    }
+}
+
+impl FromStr for IdentifierType {
+    type Err = String;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        IdentifierType::ALL.into_iter()
+            .find(|t| t.to_string() == code.trim().to_uppercase())
+            .ok_or_else(|| format!("'{}' is not a supported id qualifier", code))
+    }
+}
+
+impl TryFrom<String> for IdentifierType {
+    type Error = String;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        code.parse()
     }
 }
 
@@ -159,7 +279,9 @@ pub struct Line {
 
 #[derive(Debug, Serialize)]
 pub struct Pricing {
-    pub price: f32,
+    // written as an exact json number - through f64, 17 digit prices would lose their last digit
+    #[serde(with = "rust_decimal::serde::arbitrary_precision")]
+    pub price: Decimal,
     pub quantity: i32,
     pub uom: String,
     pub datetimes: Vec<Datetime>,
@@ -177,7 +299,8 @@ pub struct Address {
 pub struct Datetime {
     #[serde(rename = "datetimeType")]
     pub datetime_type: String,
-    pub datetime: String,
+    #[serde(serialize_with = "serialize_payload_date")]
+    pub datetime: NaiveDate,
 }
 
 #[derive(Debug, Serialize)]
@@ -200,17 +323,20 @@ impl Payload {
 }
 
 impl Document {
+    // the control number is assigned from the registry once the document is complete
     pub fn new(document_type: DocumentType) -> Self {
-        let control_number = ControlNumber::to_i32(ControlNumber::create().as_str());
-        
         Self {
             document_type: document_type.to_string(),
-            control_number,
+            control_number: 0,
             datetimes: Vec::new(),
             contracts: Vec::new(),
         }
     }
 
+    pub fn set_control_number(&mut self, control_number: i32) {
+        self.control_number = control_number;
+    }
+
     pub fn add_datetime(&mut self, datetime: Datetime) {
         self.datetimes.push(datetime);
     }
@@ -221,13 +347,13 @@ impl Document {
 }
 
 impl Contract {
-    pub fn new(contract_id: &str) -> Self {
+    pub fn new(contract_id: &str, manufacturer: &Manufacturer) -> Self {
         let references = vec![Reference::new(
             ReferenceType::ContractType, "LOI"),
             Reference::new(ReferenceType::BuyersContractNumber, contract_id),
         ];
         
-        let dealers: Vec<Dealer> = vec![Dealer::new(DealerType::Manufacturer, MANUFACTURER, IdentifierType::Duns, DUNS_NUMBER)];
+        let dealers: Vec<Dealer> = vec![Dealer::new(DealerType::Manufacturer, manufacturer.name.as_str(), IdentifierType::Duns, manufacturer.duns.as_str())];
 
         Self {            
             contract_id: contract_id.to_string(),            
@@ -318,19 +444,19 @@ impl Dealer {
 }
 
 impl Datetime {
-    pub fn new(datetime_type: DatetimeType, datetime: &str) -> Self {
+    pub fn new(datetime_type: DatetimeType, datetime: NaiveDate) -> Self {
         Self {
             datetime_type: datetime_type.to_string(),
-            datetime: datetime.to_string(),
+            datetime,
         }
     }
 }
 
 impl Pricing {
-    pub fn new(price: f32, quantity: i32, uom: &str, start: &str, end: &str) -> Self {
+    pub fn new(price: Decimal, quantity: i32, uom: &str, start: NaiveDate, end: NaiveDate) -> Self {
         Self {
-            price: price,
-            quantity: quantity,
+            price,
+            quantity,
             uom: uom.to_string(),
             datetimes: vec![
                 Datetime::new(DatetimeType::AgreementEffective, start),
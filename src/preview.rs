@@ -0,0 +1,221 @@
+use crate::payload::*;
+
+// `build` output: an aligned plain text table for the terminal, markdown for
+// pasting into a ticket or email, or the payload json itself
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Table,
+    Markdown,
+    Json,
+}
+
+struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+enum Block {
+    Heading(String),
+    Field(&'static str, String),
+    Table(Table),
+}
+
+// payload codes spelled out, with the code kept alongside
+fn purpose(code: &str) -> String {
+    let name = match code {
+        "00" => "new",
+        "04" => "change",
+        "18" => "resubmit",
+        "30" => "renew",
+        "56" => "cancel",
+        _ => "unknown",
+    };
+
+    format!("{} ({})", name, code)
+}
+
+fn datetime_name(code: &str) -> &str {
+    match code {
+        "092" => "effective",
+        "093" => "expires",
+        "094" => "prior expiration",
+        "129" => "effective",
+        "130" => "expires",
+        code => code,
+    }
+}
+
+fn change_name(code: &str) -> &str {
+    match code {
+        "AI" => "add",
+        "CI" => "change",
+        "DI" => "delete",
+        code => code,
+    }
+}
+
+fn dealer_name(code: &str) -> &str {
+    match code {
+        "MF" => "manufacturer",
+        "BG" => "buying group",
+        "EB" => "end user",
+        "DS" => "distributor",
+        code => code,
+    }
+}
+
+fn find_date(datetimes: &[Datetime], code: &str) -> String {
+    datetimes.iter()
+        .find(|datetime| datetime.datetime_type == code)
+        .map_or(String::new(), |datetime| datetime.datetime.to_string())
+}
+
+fn dates(datetimes: &[Datetime]) -> String {
+    datetimes.iter()
+        .map(|datetime| format!("{} {}", datetime_name(datetime.datetime_type.as_str()), datetime.datetime))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn references(references: &[Reference]) -> String {
+    references.iter()
+        .map(|reference| format!("{} {}", reference.reference_type, reference.reference).trim_end().to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn address(address: &Address) -> String {
+    let city_line = format!("{} {} {}", address.city, address.state, address.zip);
+
+    [address.addr1.trim(), city_line.trim()].iter()
+        .filter(|part| !part.is_empty())
+        .cloned()
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn dealer_table(dealers: &[Dealer]) -> Table {
+    Table {
+        headers: vec!["type", "name", "id", "address", "references", "effective", "expires"],
+        rows: dealers.iter().map(|dealer| vec![
+            format!("{} ({})", dealer_name(dealer.group_type.as_str()), dealer.group_type),
+            dealer.group_name.clone(),
+            format!("{} {}", dealer.id_type, dealer.id),
+            address(&dealer.address),
+            references(&dealer.references),
+            find_date(&dealer.datetimes, "129"),
+            find_date(&dealer.datetimes, "130"),
+        ]).collect(),
+    }
+}
+
+// one row per pricing tier, so a line with several tiers spans several rows
+fn line_table(agreements: &[Agreement]) -> Table {
+    let mut rows = Vec::new();
+
+    for agreement in agreements.iter() {
+        let part = agreement.line_information.iter().map(|line| line.item_id.as_str()).collect::<Vec<&str>>().join(", ");
+        let description = agreement.details.iter().map(|detail| detail.description.as_str()).collect::<Vec<&str>>().join(", ");
+
+        for pricing in agreement.pricing.iter() {
+            rows.push(vec![
+                agreement.line_number.to_string(),
+                part.clone(),
+                description.clone(),
+                change_name(agreement.reference.as_str()).to_string(),
+                pricing.price.normalize().to_string(),
+                pricing.quantity.to_string(),
+                pricing.uom.clone(),
+                find_date(&pricing.datetimes, "129"),
+                find_date(&pricing.datetimes, "130"),
+            ]);
+        }
+    }
+
+    Table {
+        headers: vec!["line", "part", "description", "change", "price", "qty", "uom", "effective", "expires"],
+        rows,
+    }
+}
+
+fn blocks(payload: &Payload) -> Vec<Block> {
+    let mut blocks = Vec::new();
+
+    for document in payload.documents.iter() {
+        for contract in document.contracts.iter() {
+            blocks.push(Block::Heading(format!("Contract {} - {}", contract.contract_id, purpose(document.document_type.as_str()))));
+            blocks.push(Block::Field("dates", dates(&document.datetimes)));
+            blocks.push(Block::Field("references", references(&contract.references)));
+            blocks.push(Block::Field("dealers", contract.dealers.len().to_string()));
+            blocks.push(Block::Table(dealer_table(&contract.dealers)));
+            blocks.push(Block::Field("lines", contract.agreements.len().to_string()));
+            blocks.push(Block::Table(line_table(&contract.agreements)));
+        }
+    }
+
+    blocks
+}
+
+fn render_table(table: &Table) -> String {
+    let mut widths: Vec<usize> = table.headers.iter().map(|header| header.len()).collect();
+    for row in table.rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| -> String {
+        let padded: Vec<String> = cells.iter().zip(widths.iter()).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        format!("  {}\n", padded.join("  ").trim_end())
+    };
+
+    let headers: Vec<String> = table.headers.iter().map(|header| header.to_uppercase()).collect();
+    let rules: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+
+    let mut output = line(&headers);
+    output.push_str(line(&rules).as_str());
+    for row in table.rows.iter() {
+        output.push_str(line(row).as_str());
+    }
+
+    output
+}
+
+// pipes would break a markdown table cell
+fn escape(cell: &str) -> String {
+    cell.replace('|', "\\|")
+}
+
+fn render_markdown_table(table: &Table) -> String {
+    let mut output = format!("| {} |\n", table.headers.join(" | "));
+    output.push_str(format!("|{}\n", " --- |".repeat(table.headers.len())).as_str());
+    for row in table.rows.iter() {
+        output.push_str(format!("| {} |\n", row.iter().map(|cell| escape(cell)).collect::<Vec<String>>().join(" | ")).as_str());
+    }
+
+    output
+}
+
+pub fn render(payload: &Payload, format: ReportFormat) -> Result<String, Box<dyn std::error::Error>> {
+    let markdown = match format {
+        ReportFormat::Json => return Ok(format!("{}\n", serde_json::to_string_pretty(payload)?)),
+        ReportFormat::Table => false,
+        ReportFormat::Markdown => true,
+    };
+
+    let mut output = String::new();
+
+    for block in blocks(payload) {
+        let text = match (markdown, block) {
+            (false, Block::Heading(text)) => format!("\n{}\n{}\n", text, "=".repeat(text.chars().count())),
+            (false, Block::Field(name, value)) => format!("{:<12}{}\n", format!("{}:", name), value),
+            (false, Block::Table(table)) => render_table(&table),
+            (true, Block::Heading(text)) => format!("\n## {}\n\n", text),
+            (true, Block::Field(name, value)) => format!("- **{}:** {}\n", name, escape(value.as_str())),
+            (true, Block::Table(table)) => format!("\n{}\n", render_markdown_table(&table)),
+        };
+        output.push_str(text.as_str());
+    }
+
+    Ok(output.trim_start().to_string())
+}
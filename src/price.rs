@@ -0,0 +1,135 @@
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Deserialize;
+
+// X12 R (decimal numeric) elements such as CTP03 carry at most 17 digits,
+// not counting the sign or the decimal point
+pub const MAX_R_DIGITS: usize = 17;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    #[default]
+    HalfUp,
+    HalfEven,
+    Down,
+    Up,
+}
+
+impl Rounding {
+    fn strategy(&self) -> RoundingStrategy {
+        match self {
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            Rounding::Down => RoundingStrategy::ToZero,
+            Rounding::Up => RoundingStrategy::AwayFromZero,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PriceFormat {
+    #[serde(default = "default_scale")]
+    pub scale: u32,
+    #[serde(default)]
+    pub rounding: Rounding,
+}
+
+fn default_scale() -> u32 {
+    4
+}
+
+impl Default for PriceFormat {
+    fn default() -> Self {
+        Self {
+            scale: default_scale(),
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+impl PriceFormat {
+    // rounds to the partner's scale; trailing zeros are dropped since R elements do not need them
+    pub fn apply(&self, price: Decimal) -> Decimal {
+        price.round_dp_with_strategy(self.scale, self.rounding.strategy()).normalize()
+    }
+}
+
+// digits an R element needs to carry the price exactly, e.g. 1234567.89 -> 9, 0.005 -> 3
+pub fn r_digits(price: &Decimal) -> usize {
+    let price = price.normalize();
+    let mantissa = price.mantissa().unsigned_abs().to_string().len();
+
+    mantissa.max(price.scale() as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn price(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    fn applied(scale: u32, rounding: Rounding, value: &str) -> String {
+        PriceFormat { scale, rounding }.apply(price(value)).to_string()
+    }
+
+    #[test]
+    fn each_rounding_mode_settles_a_midpoint_its_own_way() {
+        let modes = [(Rounding::HalfUp, "12.35", "12.25"), (Rounding::HalfEven, "12.34", "12.24"), (Rounding::Down, "12.34", "12.24"), (Rounding::Up, "12.35", "12.25")];
+
+        for (rounding, up, down) in modes {
+            assert_eq!(applied(2, rounding, "12.345"), up, "{:?}", rounding);
+            assert_eq!(applied(2, rounding, "12.245"), down, "{:?}", rounding);
+        }
+        assert_eq!(applied(2, Rounding::HalfEven, "12.355"), "12.36");
+    }
+
+    #[test]
+    fn down_and_up_ignore_the_midpoint() {
+        assert_eq!(applied(2, Rounding::Down, "12.349"), "12.34");
+        assert_eq!(applied(2, Rounding::Up, "12.341"), "12.35");
+        assert_eq!(applied(2, Rounding::Down, "-12.349"), "-12.34");
+        assert_eq!(applied(2, Rounding::Up, "-12.341"), "-12.35");
+    }
+
+    #[test]
+    fn scale_sets_the_decimals_kept() {
+        assert_eq!(applied(0, Rounding::HalfUp, "12.5"), "13");
+        assert_eq!(applied(2, Rounding::HalfUp, "12.345"), "12.35");
+        assert_eq!(applied(4, Rounding::HalfUp, "12.345"), "12.345");
+        assert_eq!(applied(4, Rounding::HalfUp, "0.123456"), "0.1235");
+    }
+
+    #[test]
+    fn trailing_zeros_are_dropped() {
+        assert_eq!(applied(4, Rounding::HalfUp, "12.5000"), "12.5");
+        assert_eq!(applied(2, Rounding::HalfUp, "12.001"), "12");
+    }
+
+    #[test]
+    fn the_default_is_four_places_half_up() {
+        assert_eq!(PriceFormat::default().apply(price("1.23455")).to_string(), "1.2346");
+    }
+
+    #[test]
+    fn r_digits_counts_what_an_r_element_carries() {
+        assert_eq!(r_digits(&price("1234567.89")), 9);
+        assert_eq!(r_digits(&price("0.005")), 3);
+        assert_eq!(r_digits(&price("12.5000")), 3);
+        assert_eq!(r_digits(&price("-12.5")), 3);
+        assert_eq!(r_digits(&price("0")), 1);
+    }
+
+    #[test]
+    fn seventeen_digits_is_the_limit() {
+        assert_eq!(r_digits(&price("1234567890123.4567")), MAX_R_DIGITS);
+        assert!(r_digits(&price("12345678901234.5678")) > MAX_R_DIGITS);
+
+        // rounding to the scale can bring a price back within the limit
+        let rounded = PriceFormat { scale: 2, rounding: Rounding::HalfUp }.apply(price("123456789012345.6789"));
+        assert_eq!(r_digits(&rounded), MAX_R_DIGITS);
+    }
+}
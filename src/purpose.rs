@@ -0,0 +1,92 @@
+use crate::payload::DocumentType;
+
+// how the new end date (-n) has to relate to the current one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewExpiration {
+    // may be given, e.g. a new contract extending an old one
+    Optional,
+    // required and no earlier than the current end date
+    Later,
+    // required and no later than the current end date
+    Earlier,
+}
+
+// what a purpose puts in its document; every purpose is assembled the same way
+// and only these switches differ, so a new purpose code is one more policy
+#[derive(Debug)]
+pub struct PurposePolicy {
+    // ZZ reference to the contract being replaced (-o)
+    pub outgoing_reference: bool,
+    // N1 loops for the buyers
+    pub dealers: bool,
+    // TD reference on each buyer, from the csv change column
+    pub distributor_reference: bool,
+    // LIN loops for the contract lines
+    pub lines: bool,
+    // buyers and lines may be computed from previous snapshots of the csv files
+    pub diff: bool,
+    pub new_expiration: NewExpiration,
+}
+
+impl PurposePolicy {
+    // renew and cancel only move dates, so they read no csv files
+    pub fn reads_inputs(&self) -> bool {
+        self.dealers || self.lines
+    }
+}
+
+const NEW: PurposePolicy = PurposePolicy {
+    outgoing_reference: true,
+    dealers: true,
+    distributor_reference: true,
+    lines: true,
+    diff: false,
+    new_expiration: NewExpiration::Optional,
+};
+
+// the csv files hold only the changes, or they are diffed against their previous snapshots
+const CHANGE: PurposePolicy = PurposePolicy {
+    outgoing_reference: false,
+    dealers: true,
+    distributor_reference: false,
+    lines: true,
+    diff: true,
+    new_expiration: NewExpiration::Optional,
+};
+
+const RESUBMIT: PurposePolicy = PurposePolicy {
+    outgoing_reference: true,
+    dealers: true,
+    distributor_reference: false,
+    lines: true,
+    diff: false,
+    new_expiration: NewExpiration::Optional,
+};
+
+const RENEW: PurposePolicy = PurposePolicy {
+    outgoing_reference: false,
+    dealers: false,
+    distributor_reference: false,
+    lines: false,
+    diff: false,
+    new_expiration: NewExpiration::Later,
+};
+
+const CANCEL: PurposePolicy = PurposePolicy {
+    outgoing_reference: false,
+    dealers: false,
+    distributor_reference: false,
+    lines: false,
+    diff: false,
+    new_expiration: NewExpiration::Earlier,
+};
+
+pub fn policy(purpose: DocumentType) -> &'static PurposePolicy {
+    match purpose {
+        DocumentType::New => &NEW,
+        DocumentType::Change => &CHANGE,
+        DocumentType::Resubmit => &RESUBMIT,
+        DocumentType::Renew => &RENEW,
+        DocumentType::Cancel => &CANCEL,
+    }
+}
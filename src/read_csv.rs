@@ -1,17 +1,40 @@
 use std::{path::Path};
+use rust_decimal::Decimal;
 use serde::{de::DeserializeOwned, Deserialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ContractData {
     pub part: String,
-    pub price: f32,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub price: Decimal,
     pub description: String,    
     pub start: String,
     pub end: String,
     pub purpose: String,
+    // optional columns, the pack falls back to the [units] table in config
+    #[serde(default)]
+    pub uom: String,
+    #[serde(default)]
+    pub quantity: Option<i32>,
 }
 
-#[derive(Debug, Deserialize)]
+// consecutive rows of the same part are one line priced in several tiers, e.g. a
+// price increase mid-contract; a part that comes back later is another line, which
+// the duplicate_part rule reports
+pub fn by_part(rows: &[ContractData]) -> Vec<Vec<&ContractData>> {
+    let mut parts: Vec<Vec<&ContractData>> = Vec::new();
+
+    for row in rows.iter() {
+        match parts.last_mut() {
+            Some(tiers) if tiers[0].part == row.part => tiers.push(row),
+            _ => parts.push(vec![row]),
+        }
+    }
+
+    parts
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct EndBuyerData {
     pub name: String,
     pub id: String,
@@ -22,18 +45,52 @@ pub struct EndBuyerData {
     pub change: String,
     pub start: String,
     pub end: String,
+    // optional columns, otherwise the [dealers] rules in config decide
+    #[serde(default)]
+    pub entity: String,
+    #[serde(default)]
+    pub qualifier: String,
+}
+
+// headers and values are trimmed, so ` delete` is read (and validated) as `delete`
+pub fn csv_reader(path: &Path) -> Result<csv::Reader<std::fs::File>, csv::Error> {
+    csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)
 }
 
 pub fn parse_csv<T>(path: &Path) -> Result<Vec<T>, Box<dyn std::error::Error>> where
     T: DeserializeOwned {
-    let mut reader = csv::Reader::from_path(path)?;
+    let mut reader = csv_reader(path)?;
     
+    // csv errors already carry the record and line they came from
     let records: Vec<T> = reader
         .deserialize()
-        .map(|record| {
-            record.expect("Unable to parse CSV")
-        })
-        .collect();
+        .collect::<Result<Vec<T>, csv::Error>>()?;
 
     Ok(records)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(part: &str) -> ContractData {
+        ContractData {
+            part: part.to_string(),
+            price: Decimal::ONE,
+            description: String::new(),
+            start: String::new(),
+            end: String::new(),
+            purpose: String::new(),
+            uom: String::new(),
+            quantity: None,
+        }
+    }
+
+    #[test]
+    fn only_consecutive_rows_of_a_part_are_tiers_of_one_line() {
+        let rows = vec![row("775"), row("775"), row("776"), row("775")];
+        let lines: Vec<(&str, usize)> = by_part(&rows).iter().map(|tiers| (tiers[0].part.as_str(), tiers.len())).collect();
+
+        assert_eq!(lines, vec![("775", 2), ("776", 1), ("775", 1)]);
+    }
+}
@@ -0,0 +1,402 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+
+use crate::date::X12_DATE_FORMAT;
+use crate::payload::*;
+use crate::x12::{Delimiters, Segment};
+
+// the ISA segment is fixed width, which is what makes its delimiters discoverable
+const ISA_LENGTH: usize = 106;
+
+pub fn detect_delimiters(edi: &str) -> Result<Delimiters, Box<dyn std::error::Error>> {
+    let edi = edi.trim_start();
+
+    if edi.starts_with("ISA") {
+        let isa: Vec<char> = edi.chars().take(ISA_LENGTH).collect();
+        if isa.len() < ISA_LENGTH {
+            return Err("ISA segment is shorter than 106 characters.".into());
+        }
+
+        return Ok(Delimiters {
+            element: isa[3],
+            sub_element: isa[104],
+            segment: isa[105],
+            // ISA11 only holds the repetition separator from ISA12 00501 on
+            repetition: match isa[84..89].iter().collect::<String>().as_str() >= "00501" {
+                true => Some(isa[82]),
+                false => None,
+            },
+        });
+    }
+
+    // a bare transaction set - the element separator follows the segment id, the
+    // terminator is the first character after ST01/ST02 that is not part of them
+    if edi.starts_with("ST") {
+        let element = edi.chars().nth(2).ok_or("Transaction set is empty.")?;
+        let segment = edi.chars()
+            .skip(3)
+            .find(|c| !c.is_ascii_alphanumeric() && *c != element)
+            .ok_or("Unable to find a segment terminator.")?;
+
+        return Ok(Delimiters {
+            element,
+            sub_element: Delimiters::default().sub_element,
+            segment,
+            repetition: None,
+        });
+    }
+
+    Err("Input does not start with an ISA or ST segment.".into())
+}
+
+pub fn tokenize(edi: &str) -> Result<(Delimiters, Vec<Segment>), Box<dyn std::error::Error>> {
+    let delimiters = detect_delimiters(edi)?;
+
+    let segments = edi
+        .split(delimiters.segment)
+        .map(|segment| segment.trim_matches(|c: char| c == '\r' || c == '\n'))
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            let mut elements = segment.split(delimiters.element).map(|e| e.to_string());
+            Segment {
+                id: elements.next().unwrap_or_default(),
+                elements: elements.collect(),
+            }
+        })
+        .collect();
+
+    Ok((delimiters, segments))
+}
+
+pub fn element(segment: &Segment, position: usize) -> &str {
+    segment.elements.get(position - 1).map_or("", |e| e.as_str())
+}
+
+// DTM02 is CCYYMMDD
+fn datetime(segment: &Segment) -> Result<Datetime, Box<dyn std::error::Error>> {
+    let date = element(segment, 2);
+
+    Ok(Datetime {
+        datetime_type: element(segment, 1).to_string(),
+        datetime: NaiveDate::parse_from_str(date, X12_DATE_FORMAT).map_err(|_| format!("DTM*{} has '{}', not a CCYYMMDD date.", element(segment, 1), date))?,
+    })
+}
+
+fn reference(segment: &Segment) -> Reference {
+    Reference {
+        reference_type: element(segment, 1).to_string(),
+        reference: element(segment, 2).to_string(),
+    }
+}
+
+// which loop a DTM or REF belongs to depends on the last loop that was opened
+enum Position {
+    Header,
+    Dealer,
+    Line,
+    Pricing,
+}
+
+struct TransactionSet {
+    document: Document,
+    contract: Contract,
+    position: Position,
+    segment_count: usize,
+}
+
+impl TransactionSet {
+    fn dealer(&mut self) -> Result<&mut Dealer, Box<dyn std::error::Error>> {
+        Ok(self.contract.dealers.last_mut().ok_or("Segment found outside of an N1 loop.")?)
+    }
+
+    fn agreement(&mut self) -> Result<&mut Agreement, Box<dyn std::error::Error>> {
+        Ok(self.contract.agreements.last_mut().ok_or("Segment found outside of a LIN loop.")?)
+    }
+
+    fn pricing(&mut self) -> Result<&mut Pricing, Box<dyn std::error::Error>> {
+        Ok(self.agreement()?.pricing.last_mut().ok_or("DTM found outside of a CTP loop.")?)
+    }
+
+    fn apply(&mut self, segment: &Segment, delimiters: &Delimiters) -> Result<(), Box<dyn std::error::Error>> {
+        match segment.id.as_str() {
+            "BCT" => {
+                self.contract.contract_id = element(segment, 2).to_string();
+                self.document.document_type = element(segment, 10).to_string();
+            },
+            "DTM" => match self.position {
+                Position::Header => self.document.add_datetime(datetime(segment)?),
+                Position::Dealer => self.dealer()?.datetimes.push(datetime(segment)?),
+                Position::Line => return Err("DTM found in a LIN loop before any CTP.".into()),
+                Position::Pricing => self.pricing()?.datetimes.push(datetime(segment)?),
+            },
+            "REF" => match self.position {
+                Position::Header => self.contract.references.push(reference(segment)),
+                Position::Dealer => self.dealer()?.references.push(reference(segment)),
+                Position::Line | Position::Pricing => self.agreement()?.reference = element(segment, 2).to_string(),
+            },
+            "N1" => {
+                self.position = Position::Dealer;
+                self.contract.dealers.push(Dealer {
+                    group_type: element(segment, 1).to_string(),
+                    group_name: element(segment, 2).to_string(),
+                    id_type: element(segment, 3).to_string(),
+                    id: element(segment, 4).to_string(),
+                    address: Address::new("", "", "", ""),
+                    references: Vec::new(),
+                    datetimes: Vec::new(),
+                });
+            },
+            "N3" => self.dealer()?.address.addr1 = element(segment, 1).to_string(),
+            "N4" => {
+                let address = &mut self.dealer()?.address;
+                address.city = element(segment, 1).to_string();
+                address.state = element(segment, 2).to_string();
+                address.zip = element(segment, 3).to_string();
+            },
+            "LIN" => {
+                self.position = Position::Line;
+
+                let line_number: i32 = element(segment, 1).parse()
+                    .map_err(|_| format!("LIN01 '{}' is not a line number.", element(segment, 1)))?;
+
+                let mut agreement = Agreement::new(&line_number, "");
+                // LIN02 onwards are qualifier / item id pairs
+                for pair in segment.elements[1..].chunks(2) {
+                    if let [_, item_id] = pair {
+                        agreement.add_line(&line_number, item_id);
+                    }
+                }
+
+                self.contract.add_agreement(agreement);
+            },
+            "PID" => {
+                let description = element(segment, 5).to_string();
+                self.agreement()?.add_detail(description.as_str());
+            },
+            "CTP" => {
+                self.position = Position::Pricing;
+
+                let price: Decimal = element(segment, 3).parse()
+                    .map_err(|_| format!("CTP03 '{}' is not a price.", element(segment, 3)))?;
+                let quantity: i32 = match element(segment, 4) {
+                    "" => 1,
+                    quantity => quantity.parse().map_err(|_| format!("CTP04 '{}' is not a quantity.", quantity))?,
+                };
+                // CTP05 is a composite, the unit of measure is its first component
+                let uom = element(segment, 5).split(delimiters.sub_element).next().unwrap_or_default().to_string();
+
+                self.agreement()?.add_pricing(Pricing {
+                    price,
+                    quantity,
+                    uom,
+                    datetimes: Vec::new(),
+                });
+            },
+            // segments we do not model are skipped rather than rejected
+            _ => {},
+        }
+
+        Ok(())
+    }
+}
+
+// rebuilds a payload from X12 text, with or without an interchange envelope,
+// one document per ST/SE transaction set
+pub fn parse_x12(edi: &str) -> Result<Payload, Box<dyn std::error::Error>> {
+    let (delimiters, segments) = tokenize(edi)?;
+
+    let mut payload = Payload::new();
+    let mut current: Option<TransactionSet> = None;
+
+    for segment in segments.iter() {
+        match segment.id.as_str() {
+            "ST" => {
+                if current.is_some() {
+                    return Err("ST found before the previous transaction set was closed.".into());
+                }
+                if element(segment, 1) != "845" {
+                    return Err(format!("Transaction set {} is not an 845.", element(segment, 1)).into());
+                }
+
+                let mut document = Document::new(DocumentType::New);
+                document.set_control_number(element(segment, 2).parse()
+                    .map_err(|_| format!("ST02 '{}' is not a control number.", element(segment, 2)))?);
+
+                current = Some(TransactionSet {
+                    document,
+                    contract: Contract {
+                        contract_id: String::new(),
+                        references: Vec::new(),
+                        dealers: Vec::new(),
+                        agreements: Vec::new(),
+                    },
+                    position: Position::Header,
+                    segment_count: 1,
+                });
+            },
+            "SE" => {
+                let mut set = current.take().ok_or("SE found outside of a transaction set.")?;
+                set.segment_count += 1;
+
+                let expected: usize = element(segment, 1).parse()
+                    .map_err(|_| format!("SE01 '{}' is not a segment count.", element(segment, 1)))?;
+                if expected != set.segment_count {
+                    return Err(format!("Transaction set {} declares {} segments but contains {}.", element(segment, 2), expected, set.segment_count).into());
+                }
+
+                set.document.add_contract(set.contract);
+                payload.add_document(set.document);
+            },
+            _ => {
+                if let Some(set) = current.as_mut() {
+                    set.segment_count += 1;
+                    set.apply(segment, &delimiters)?;
+                }
+            },
+        }
+    }
+
+    if current.is_some() {
+        return Err("Transaction set is missing its SE segment.".into());
+    }
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::contract::{Buyer, ContractBuilder, LineItem};
+    use crate::envelope::Envelope;
+    use crate::x12::{encode, transaction_sets};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn payload() -> Payload {
+        let mut document = ContractBuilder::new("C100")
+            .purpose(DocumentType::New)
+            .effective(date(2023, 1, 1))
+            .expires(date(2023, 12, 31))
+            .replaces("C099")
+            .buyer(Buyer::new(DealerType::Distributor, "ACME HOSPITAL", IdentifierType::Dea, "9988")
+                .address("1 Main St", "Hauppauge", "NY", "11788")
+                .distributor_reference("DIST1")
+                .effective(date(2023, 2, 1)))
+            .line(LineItem::new("775", "GAUZE PAD", Decimal::new(12, 0))
+                .pack("BX", 10)
+                .expires(date(2023, 6, 30))
+                .tier(Decimal::new(135, 1))
+                .effective(date(2023, 7, 1)))
+            .line(LineItem::new("776", "SPONGE", Decimal::new(5, 0)))
+            .build()
+            .unwrap();
+        document.set_control_number(7);
+
+        let mut payload = Payload::new();
+        payload.add_document(document);
+        payload
+    }
+
+    fn envelope(separators: &str) -> Envelope {
+        toml::from_str(format!(r#"
+            sender_qualifier = "ZZ"
+            sender_id = "BUSSE"
+            receiver_qualifier = "01"
+            receiver_id = "123456789"
+            application_sender = "BUSSE"
+            application_receiver = "PARTNER"
+            {}
+        "#, separators).as_str()).unwrap()
+    }
+
+    fn round_trip(envelope: &Envelope) -> (String, Payload) {
+        let original = payload();
+        let segments = envelope.wrap(transaction_sets(&original), "1", "1");
+        let edi = encode(&segments, &envelope.delimiters()).unwrap();
+
+        (edi.clone(), parse_x12(edi.as_str()).unwrap())
+    }
+
+    #[test]
+    fn encoded_payload_parses_back_to_the_same_payload() {
+        let (_, parsed) = round_trip(&envelope(""));
+
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(payload()).unwrap());
+    }
+
+    #[test]
+    fn round_trip_keeps_every_pricing_tier() {
+        let (_, parsed) = round_trip(&envelope(""));
+        let pricing = &parsed.documents[0].contracts[0].agreements[0].pricing;
+
+        assert_eq!(pricing.len(), 2);
+        assert_eq!(pricing[1].price, Decimal::new(135, 1));
+        assert_eq!(pricing[1].uom, "BX");
+        assert_eq!(pricing[1].datetimes[0].datetime, date(2023, 7, 1));
+    }
+
+    #[test]
+    fn delimiters_are_read_from_the_isa_segment() {
+        let (edi, parsed) = round_trip(&envelope(r#"
+            element_separator = "|"
+            sub_element_separator = ":"
+            segment_terminator = "!"
+        "#));
+        let delimiters = detect_delimiters(edi.as_str()).unwrap();
+
+        assert_eq!((delimiters.element, delimiters.sub_element, delimiters.segment, delimiters.repetition), ('|', ':', '!', None));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), serde_json::to_value(payload()).unwrap());
+    }
+
+    #[test]
+    fn the_repetition_separator_is_read_from_a_005010_isa() {
+        let (edi, _) = round_trip(&envelope(r#"
+            version = "005010"
+            repetition_separator = "{"
+        "#));
+
+        assert_eq!(detect_delimiters(edi.as_str()).unwrap().repetition, Some('{'));
+    }
+
+    #[test]
+    fn delimiters_are_found_in_a_bare_transaction_set() {
+        let delimiters = detect_delimiters("ST|845|0001~BCT|PS|C100~SE|3|0001~").unwrap();
+
+        assert_eq!((delimiters.element, delimiters.segment), ('|', '~'));
+    }
+
+    #[test]
+    fn short_isa_is_rejected() {
+        assert!(detect_delimiters("ISA*00*~").is_err());
+        assert!(detect_delimiters("GS*PC~").is_err());
+    }
+
+    #[test]
+    fn segment_count_must_match_se01() {
+        let error = parse_x12("ST*845*0001~BCT*PS*C100********00~SE*5*0001~").unwrap_err();
+
+        assert_eq!(error.to_string(), "Transaction set 0001 declares 5 segments but contains 3.");
+    }
+
+    #[test]
+    fn malformed_transaction_sets_are_rejected() {
+        let cases = [
+            ("ST*810*0001~SE*2*0001~", "Transaction set 810 is not an 845."),
+            ("ST*845*0001~BCT*PS*C100~", "Transaction set is missing its SE segment."),
+            ("ST*845*0001~ST*845*0002~", "ST found before the previous transaction set was closed."),
+            ("ST*845*0001~LIN*X*VC*775~SE*3*0001~", "LIN01 'X' is not a line number."),
+            ("ST*845*0001~LIN*1*VC*775~CTP**CON*abc~SE*4*0001~", "CTP03 'abc' is not a price."),
+            ("ST*845*0001~DTM*092*2023-01-01~SE*3*0001~", "DTM*092 has '2023-01-01', not a CCYYMMDD date."),
+            ("ST*845*0001~N3*1 Main St~SE*3*0001~", "Segment found outside of an N1 loop."),
+        ];
+
+        for (edi, message) in cases {
+            assert_eq!(parse_x12(edi).unwrap_err().to_string(), message, "{}", edi);
+        }
+    }
+}
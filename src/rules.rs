@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::payload::*;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    // stops the run, nothing is sent
+    Error,
+    // printed, the run goes on
+    Warning,
+    Off,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Off => "off",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// [rules] in config.toml (or a partner profile) - each rule is error, warning or off
+#[derive(Deserialize, Debug, Clone)]
+pub struct RuleSettings {
+    // a line's pricing window starts before or ends after the contract
+    #[serde(default = "error")]
+    pub line_window: Severity,
+    // a buyer's agreement dates fall outside the contract
+    #[serde(default = "warning")]
+    pub buyer_window: Severity,
+    // two pricing tiers of one line in force on the same day
+    #[serde(default = "error")]
+    pub tier_overlap: Severity,
+    // the same part on more than one line
+    #[serde(default = "error")]
+    pub duplicate_part: Severity,
+    // the same buyer id on more than one N1 loop
+    #[serde(default = "error")]
+    pub duplicate_buyer: Severity,
+    // a price of zero or less
+    #[serde(default = "error")]
+    pub price: Severity,
+    // a change document without any buyer or line to change
+    #[serde(default = "error")]
+    pub empty_change: Severity,
+    // a cancel document that carries lines
+    #[serde(default = "error")]
+    pub cancel_lines: Severity,
+}
+
+fn error() -> Severity {
+    Severity::Error
+}
+
+fn warning() -> Severity {
+    Severity::Warning
+}
+
+impl Default for RuleSettings {
+    fn default() -> Self {
+        Self {
+            line_window: error(),
+            buyer_window: warning(),
+            tier_overlap: error(),
+            duplicate_part: error(),
+            duplicate_buyer: error(),
+            price: error(),
+            empty_change: error(),
+            cancel_lines: error(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub contract: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}] contract {}: {}", self.severity, self.rule, self.contract, self.message)
+    }
+}
+
+fn find_date(datetimes: &[Datetime], datetime_type: DatetimeType) -> Option<NaiveDate> {
+    let code = datetime_type.to_string();
+
+    datetimes.iter().find(|datetime| datetime.datetime_type == code).map(|datetime| datetime.datetime)
+}
+
+// the findings for one contract, with the severity each rule is configured at
+struct Checker<'a> {
+    settings: &'a RuleSettings,
+    contract: &'a str,
+    findings: Vec<Finding>,
+}
+
+impl Checker<'_> {
+    fn add(&mut self, rule: &'static str, severity: Severity, message: String) {
+        if severity != Severity::Off {
+            self.findings.push(Finding { rule, severity, contract: self.contract.to_string(), message });
+        }
+    }
+
+    // None on either side of the contract window means that side is open
+    fn window(&mut self, rule: &'static str, severity: Severity, what: &str, datetimes: &[Datetime], contract: (Option<NaiveDate>, Option<NaiveDate>)) {
+        let start = find_date(datetimes, DatetimeType::AgreementEffective);
+        let end = find_date(datetimes, DatetimeType::AgreementExpiration);
+
+        if let (Some(start), Some(contract_start)) = (start, contract.0) {
+            if start < contract_start {
+                self.add(rule, severity, format!("{} starts {}, before the contract starts {}", what, start, contract_start));
+            }
+        }
+        if let (Some(end), Some(contract_end)) = (end, contract.1) {
+            if end > contract_end {
+                self.add(rule, severity, format!("{} ends {}, after the contract ends {}", what, end, contract_end));
+            }
+        }
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                self.add(rule, severity, format!("{} ends {} before it starts {}", what, end, start));
+            }
+        }
+    }
+
+    fn check(&mut self, document: &Document, contract: &Contract) {
+        let settings = self.settings;
+        let window = (
+            find_date(&document.datetimes, DatetimeType::ContractEffective),
+            find_date(&document.datetimes, DatetimeType::ContractExpiration),
+        );
+        // the manufacturer is always there, only the buyers count
+        let manufacturer = DealerType::Manufacturer.to_string();
+        let buyers: Vec<&Dealer> = contract.dealers.iter().filter(|dealer| dealer.group_type != manufacturer).collect();
+
+        for agreement in contract.agreements.iter() {
+            let part = agreement.line_information.iter().map(|line| line.item_id.as_str()).collect::<Vec<&str>>().join(", ");
+
+            for pricing in agreement.pricing.iter() {
+                self.window("line_window", settings.line_window, format!("line {} ({})", agreement.line_number, part).as_str(), &pricing.datetimes, window);
+
+                if pricing.price <= rust_decimal::Decimal::ZERO {
+                    self.add("price", settings.price, format!("line {} ({}) is priced at {}", agreement.line_number, part, pricing.price));
+                }
+            }
+
+            // tiers are dated by the builder, an undated one (read back from edi) is left alone
+            let tiers: Vec<(NaiveDate, NaiveDate)> = agreement.pricing.iter()
+                .filter_map(|pricing| Some((
+                    find_date(&pricing.datetimes, DatetimeType::AgreementEffective)?,
+                    find_date(&pricing.datetimes, DatetimeType::AgreementExpiration)?,
+                )))
+                .collect();
+            for (idx, (start, end)) in tiers.iter().enumerate() {
+                for (other_start, other_end) in tiers.iter().skip(idx + 1) {
+                    if start <= other_end && other_start <= end {
+                        self.add("tier_overlap", settings.tier_overlap, format!(
+                            "line {} ({}) has price tiers {} to {} and {} to {} in force on the same days",
+                            agreement.line_number, part, start, end, other_start, other_end,
+                        ));
+                    }
+                }
+            }
+        }
+
+        for buyer in buyers.iter() {
+            self.window("buyer_window", settings.buyer_window, format!("buyer {} {}", buyer.id, buyer.group_name).as_str(), &buyer.datetimes, window);
+        }
+
+        let mut parts = HashSet::new();
+        for line in contract.agreements.iter().flat_map(|agreement| agreement.line_information.iter()) {
+            if !parts.insert(line.item_id.as_str()) {
+                self.add("duplicate_part", settings.duplicate_part, format!("part {} is on more than one line", line.item_id));
+            }
+        }
+
+        let mut ids = HashSet::new();
+        for buyer in buyers.iter() {
+            if !ids.insert((buyer.id_type.as_str(), buyer.id.as_str())) {
+                self.add("duplicate_buyer", settings.duplicate_buyer, format!("buyer {} {} is listed more than once", buyer.id_type, buyer.id));
+            }
+        }
+
+        if document.document_type == DocumentType::Change.to_string() && buyers.is_empty() && contract.agreements.is_empty() {
+            self.add("empty_change", settings.empty_change, "the change document has no buyers or lines to change".to_string());
+        }
+
+        if document.document_type == DocumentType::Cancel.to_string() && !contract.agreements.is_empty() {
+            self.add("cancel_lines", settings.cancel_lines, format!("the cancel document carries {} line(s)", contract.agreements.len()));
+        }
+    }
+}
+
+// 845 business rules over a built document, checked before it is numbered or sent
+pub fn check_document(document: &Document, settings: &RuleSettings) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for contract in document.contracts.iter() {
+        let mut checker = Checker {
+            settings,
+            contract: contract.contract_id.as_str(),
+            findings: Vec::new(),
+        };
+        checker.check(document, contract);
+        findings.append(&mut checker.findings);
+    }
+
+    findings
+}
+
+pub fn check_rules(payload: &Payload, settings: &RuleSettings) -> Vec<Finding> {
+    payload.documents.iter().flat_map(|document| check_document(document, settings)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::contract::{Buyer, ContractBuilder, LineItem};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn contract(purpose: DocumentType) -> ContractBuilder {
+        ContractBuilder::new("C100")
+            .purpose(purpose)
+            .effective(date(2023, 1, 1))
+            .expires(date(2023, 12, 31))
+    }
+
+    fn buyer(id: &str) -> Buyer {
+        Buyer::new(DealerType::Distributor, "ACME HOSPITAL", IdentifierType::Dea, id)
+    }
+
+    fn line(part: &str, price: i64) -> LineItem {
+        LineItem::new(part, "GAUZE PAD", Decimal::new(price, 0))
+    }
+
+    fn rules(builder: ContractBuilder, settings: &RuleSettings) -> Vec<(&'static str, Severity)> {
+        check_document(&builder.build().unwrap(), settings).iter().map(|finding| (finding.rule, finding.severity)).collect()
+    }
+
+    #[test]
+    fn a_sound_contract_has_no_findings() {
+        let builder = contract(DocumentType::New).buyer(buyer("1")).buyer(buyer("2")).line(line("775", 12)).line(line("776", 5));
+
+        assert!(rules(builder, &RuleSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn windows_outside_the_contract_are_reported() {
+        let builder = contract(DocumentType::New)
+            .buyer(buyer("1").effective(date(2022, 12, 1)))
+            .line(line("775", 12).expires(date(2024, 1, 31)))
+            .line(line("776", 5).effective(date(2023, 6, 1)).expires(date(2023, 5, 1)));
+
+        assert_eq!(rules(builder, &RuleSettings::default()), vec![
+            ("line_window", Severity::Error),
+            ("line_window", Severity::Error),
+            ("buyer_window", Severity::Warning),
+        ]);
+    }
+
+    #[test]
+    fn duplicates_and_bad_prices_are_reported() {
+        let builder = contract(DocumentType::New)
+            .buyer(buyer("1"))
+            .buyer(buyer("1"))
+            .line(line("775", 0))
+            .line(line("775", 12));
+
+        assert_eq!(rules(builder, &RuleSettings::default()), vec![
+            ("price", Severity::Error),
+            ("duplicate_part", Severity::Error),
+            ("duplicate_buyer", Severity::Error),
+        ]);
+    }
+
+    #[test]
+    fn change_and_cancel_documents_are_checked_for_their_content() {
+        assert_eq!(rules(contract(DocumentType::Change), &RuleSettings::default()), vec![("empty_change", Severity::Error)]);
+
+        // the cancel policy drops lines, so a document carrying them is put together by hand
+        let mut document = contract(DocumentType::Cancel).new_expiration(date(2023, 6, 30)).build().unwrap();
+        document.contracts[0].add_agreement(Agreement::new(&1, "AI"));
+        let findings = check_document(&document, &RuleSettings::default());
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].to_string(), "error [cancel_lines] contract C100: the cancel document carries 1 line(s)");
+    }
+
+    #[test]
+    fn overlapping_price_tiers_are_reported() {
+        let increase = line("775", 12).expires(date(2023, 6, 30)).tier(Decimal::new(13, 0)).effective(date(2023, 7, 1));
+        let overlap = line("776", 5).expires(date(2023, 7, 15)).tier(Decimal::new(6, 0)).effective(date(2023, 7, 1));
+
+        assert!(rules(contract(DocumentType::New).line(increase), &RuleSettings::default()).is_empty());
+        assert_eq!(rules(contract(DocumentType::New).line(overlap), &RuleSettings::default()), vec![("tier_overlap", Severity::Error)]);
+    }
+
+    #[test]
+    fn severities_come_from_the_settings() {
+        let settings: RuleSettings = toml::from_str("price = \"warning\"\nduplicate_part = \"off\"").unwrap();
+        let builder = contract(DocumentType::New).line(line("775", 0)).line(line("775", 12));
+
+        assert_eq!(rules(builder, &settings), vec![("price", Severity::Warning)]);
+        assert_eq!(settings.line_window, Severity::Error);
+    }
+}
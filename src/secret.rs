@@ -0,0 +1,206 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+
+// where a credential comes from in config.toml:
+//   api_key = "..."                          plaintext (kept for existing configs)
+//   api_key = { env = "STEDI_API_KEY" }      an environment variable
+//   api_key = { file = "secrets/stedi.key" } a file only its owner can read
+//   api_key = { command = "pass show stedi" } the first line a command prints
+//
+// only the source is read with the config; it is resolved when a credential is
+// actually needed, so commands that never call stedi do not need the key
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum SecretSource {
+    Plain(String),
+    Env { env: String },
+    File { file: PathBuf },
+    Command { command: String },
+}
+
+impl SecretSource {
+    pub fn resolve(&self) -> Result<Secret, String> {
+        Secret::try_from(self.clone())
+    }
+}
+
+// a plaintext key must not leak through the config's debug output either
+impl fmt::Debug for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::Plain(_) => write!(f, "Plain(\"[redacted]\")"),
+            SecretSource::Env { env } => write!(f, "Env {{ env: {:?} }}", env),
+            SecretSource::File { file } => write!(f, "File {{ file: {:?} }}", file),
+            SecretSource::Command { command } => write!(f, "Command {{ command: {:?} }}", command),
+        }
+    }
+}
+
+// a resolved credential - it never shows up in debug output
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(\"[redacted]\")")
+    }
+}
+
+impl TryFrom<SecretSource> for Secret {
+    type Error = String;
+
+    fn try_from(source: SecretSource) -> Result<Self, Self::Error> {
+        let value = match source {
+            SecretSource::Plain(value) => value,
+            SecretSource::Env { env } => std::env::var(&env)
+                .map_err(|_| format!("Environment variable {} is not set.", env))?,
+            SecretSource::File { file } => read_secret_file(&file)?,
+            SecretSource::Command { command } => run_secret_command(&command)?,
+        };
+
+        let value = value.trim().to_string();
+        if value.is_empty() {
+            return Err("A configured secret resolved to an empty value.".to_string());
+        }
+
+        Ok(Self(value))
+    }
+}
+
+// a secrets file readable by group or others is refused rather than trusted
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)
+        .map_err(|e| format!("Unable to read secrets file {}: {}", path.display(), e))?
+        .permissions()
+        .mode();
+
+    match mode & 0o077 {
+        0 => Ok(()),
+        _ => Err(format!("Secrets file {} must only be accessible by its owner (chmod 600), found {:o}.", path.display(), mode & 0o777)),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+fn read_secret_file(path: &Path) -> Result<String, String> {
+    check_permissions(path)?;
+
+    fs::read_to_string(path).map_err(|e| format!("Unable to read secrets file {}: {}", path.display(), e))
+}
+
+fn run_secret_command(command: &str) -> Result<String, String> {
+    #[cfg(windows)]
+    let output = Command::new("cmd").args(["/C", command]).output();
+    #[cfg(not(windows))]
+    let output = Command::new("sh").args(["-c", command]).output();
+
+    let output = output.map_err(|e| format!("Unable to run secret command '{}': {}", command, e))?;
+
+    if !output.status.success() {
+        return Err(format!("Secret command '{}' failed with {}.", command, output.status));
+    }
+
+    let stdout = String::from_utf8(output.stdout).map_err(|_| format!("Secret command '{}' printed invalid utf-8.", command))?;
+
+    Ok(stdout.lines().next().unwrap_or_default().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a secrets file in the temp directory, named after the test so tests can run in parallel
+    fn secret_file(name: &str, contents: &str, mode: u32) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("busse-845-secret-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        path
+    }
+
+    fn source(toml: &str) -> SecretSource {
+        #[derive(Deserialize)]
+        struct Stedi {
+            api_key: SecretSource,
+        }
+
+        toml::from_str::<Stedi>(toml).unwrap().api_key
+    }
+
+    #[test]
+    fn keys_never_show_in_debug_output() {
+        let plain = source(r#"api_key = "sk_live_1234""#);
+        assert!(!format!("{:?}", plain).contains("sk_live_1234"));
+
+        let secret = plain.resolve().unwrap();
+        assert_eq!(secret.expose(), "sk_live_1234");
+        assert!(!format!("{:?}", secret).contains("sk_live_1234"));
+    }
+
+    #[test]
+    fn an_environment_variable_is_read() {
+        let name = format!("BUSSE_845_TEST_KEY_{}", std::process::id());
+        std::env::set_var(&name, " from-env \n");
+        assert_eq!(source(&format!(r#"api_key = {{ env = "{}" }}"#, name)).resolve().unwrap().expose(), "from-env");
+
+        std::env::remove_var(&name);
+        assert!(source(&format!(r#"api_key = {{ env = "{}" }}"#, name)).resolve().is_err());
+    }
+
+    #[test]
+    fn an_owner_only_file_is_read() {
+        let path = secret_file("owner", "from-file\n", 0o600);
+        let resolved = SecretSource::File { file: path.clone() }.resolve();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(resolved.unwrap().expose(), "from-file");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_group_readable_file_is_refused() {
+        let path = secret_file("group", "from-file\n", 0o640);
+        let resolved = SecretSource::File { file: path.clone() }.resolve();
+        let _ = fs::remove_file(&path);
+
+        assert!(resolved.unwrap_err().contains("chmod 600"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_command_gives_its_first_line() {
+        assert_eq!(source(r#"api_key = { command = "printf 'from-command\nsecond'" }"#).resolve().unwrap().expose(), "from-command");
+        assert!(source(r#"api_key = { command = "exit 1" }"#).resolve().is_err());
+    }
+
+    #[test]
+    fn an_empty_value_is_an_error() {
+        assert!(source(r#"api_key = "  ""#).resolve().is_err());
+
+        let path = secret_file("empty", "\n", 0o600);
+        let resolved = SecretSource::File { file: path.clone() }.resolve();
+        let _ = fs::remove_file(&path);
+        assert!(resolved.is_err());
+    }
+}
@@ -1,42 +1,329 @@
+use std::fmt;
 use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
-use oxhttp::{Client, model::{Request, Response, HeaderName, Method}};
+use oxhttp::{Client, model::{Request, HeaderName, Method, Status}};
 
-pub fn make_api_call_to_stedi_for_edi_string(map_id: &str, api_key: &str, guide_id: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let url = format!("https://mappings.stedi.com/2021-06-01/mappings/{}/map", map_id);
-    let api_key = format!("Key {}", api_key);
+pub const ENVELOPE_FILE: &str = "stedi.json";
 
-    let combined_json = fs::read_to_string("combined.json").unwrap();    
+// raw stedi responses kept in the run directory
+const MAP_RESPONSE_FILE: &str = "stedi_map_response.json";
+const TRANSLATE_RESPONSE_FILE: &str = "stedi_translate_response.json";
 
-    let client = Client::new();
+// production endpoints, used unless [stedi] overrides them
+pub const MAPPINGS_BASE_URL: &str = "https://mappings.stedi.com/2021-06-01";
+pub const TRANSLATE_BASE_URL: &str = "https://edi-translate.us.stedi.com/2022-01-01";
 
-    let mut request_builder = Request::builder(Method::POST, url.parse().unwrap());    
-    request_builder.headers_mut().set(HeaderName::CONTENT_TYPE, "application/json".parse().unwrap());
-    request_builder.headers_mut().set(HeaderName::AUTHORIZATION, api_key.parse().unwrap());
-        
-    let response: Response = client.request(request_builder.with_body(combined_json)).unwrap();
-    
-    let body = response.into_body().to_string().unwrap();
+#[derive(Debug)]
+pub enum StediError {
+    // 401 / 403 - the api key is missing, wrong or lacks access
+    Unauthorized(String),
+    // any other non-success status, once retries (429 / 5xx) are used up
+    Status { status: u16, message: String },
+    // connection failures and timeouts, once retries are used up
+    Transport(std::io::Error),
+    // the response body was not the json we expected
+    InvalidResponse(String),
+    // the translate response had no "output" string
+    MissingOutput(String),
+    // a local input (combined.json, stedi.json) could not be read
+    Input(std::io::Error),
+    // a configured endpoint is not a valid url
+    InvalidUrl(String),
+    // a request header could not be built
+    InvalidHeader(String),
+}
 
-    // dbg!(&body);
+impl fmt::Display for StediError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StediError::Unauthorized(message) => write!(f, "Stedi rejected the api key: {}", message),
+            StediError::Status { status, message } => write!(f, "Stedi returned {}: {}", status, message),
+            StediError::Transport(e) => write!(f, "Unable to reach Stedi: {}", e),
+            StediError::InvalidResponse(body) => write!(f, "Stedi returned a response that is not valid json: {}", body),
+            StediError::MissingOutput(body) => write!(f, "Stedi returned no EDI output: {}", body),
+            StediError::Input(e) => write!(f, "Unable to read the Stedi request input: {}", e),
+            StediError::InvalidUrl(url) => write!(f, "Invalid Stedi url: {}", url),
+            StediError::InvalidHeader(header) => write!(f, "Unable to build the {} header for Stedi", header),
+        }
+    }
+}
 
-    let edi_config = fs::read_to_string("stedi.json").unwrap();
+impl std::error::Error for StediError {}
 
-    let edi_request_body = format!("{{\"guideId\": \"{}\", \"input\": {}, \"envelope\": {}}}", guide_id, body, edi_config);
+// stedi errors come back as {"message": "..."}, anything else is surfaced as-is
+fn error_message(body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(body).ok()
+        .and_then(|json| json["message"].as_str().map(|m| m.to_string()))
+        .unwrap_or_else(|| body.to_string())
+}
 
-    // dbg!(&edi_request_body);
+// oxhttp has no constant for 429
+const TOO_MANY_REQUESTS: u16 = 429;
 
-    let mut edi_request_builder = Request::builder(Method::POST, "https://edi-translate.us.stedi.com/2022-01-01/x12/from-json".parse().unwrap());    
-    edi_request_builder.headers_mut().set(HeaderName::CONTENT_TYPE, "application/json".parse().unwrap());
-    edi_request_builder.headers_mut().set(HeaderName::AUTHORIZATION, api_key.parse().unwrap());
+fn is_retryable(status: Status) -> bool {
+    *status == TOO_MANY_REQUESTS || status.is_server_error()
+}
 
-    let edi_response = client.request(edi_request_builder.with_body(edi_request_body)).unwrap();
+pub struct StediClient {
+    client: Client,
+    api_key: String,
+    mappings_base_url: String,
+    translate_base_url: String,
+    max_retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+}
 
-    let edi_response_body = edi_response.into_body().to_string().unwrap();
+impl StediClient {
+    pub fn new(api_key: &str, mappings_base_url: &str, translate_base_url: &str, timeout: Duration, max_retries: u32, backoff: Duration) -> Self {
+        let mut client = Client::new();
+        client.set_global_timeout(timeout);
 
-    let edi_response_json: serde_json::Value = serde_json::from_str(&edi_response_body).unwrap();
+        Self {
+            client,
+            api_key: format!("Key {}", api_key),
+            mappings_base_url: mappings_base_url.trim_end_matches('/').to_string(),
+            translate_base_url: translate_base_url.trim_end_matches('/').to_string(),
+            max_retries,
+            backoff,
+            timeout,
+        }
+    }
 
-    // dbg!(&edi_response_body);
+    // a Retry-After longer than the request timeout is capped at it, so a bad or
+    // hostile header cannot hang a run for hours
+    fn retry_wait(&self, retry_after: Option<Duration>, wait: Duration) -> Duration {
+        retry_after.map_or(wait, |retry_after| retry_after.min(self.timeout))
+    }
 
-    Ok(edi_response_json["output"].to_string())
-}
\ No newline at end of file
+    fn request(&self, url: &str, body: &str) -> Result<Request, StediError> {
+        let url = url.parse().map_err(|e| StediError::InvalidUrl(format!("{} ({})", url, e)))?;
+
+        let mut request_builder = Request::builder(Method::POST, url);
+        request_builder.headers_mut().set(HeaderName::CONTENT_TYPE, "application/json".parse()
+            .map_err(|_| StediError::InvalidHeader("Content-Type".to_string()))?);
+        request_builder.headers_mut().set(HeaderName::AUTHORIZATION, self.api_key.parse()
+            .map_err(|_| StediError::Unauthorized("the api key contains characters not allowed in a header".to_string()))?);
+
+        Ok(request_builder.with_body(body.to_string()))
+    }
+
+    // retries 429s, 5xxs and transport failures with exponential backoff,
+    // honouring Retry-After when stedi sends one
+    fn post(&self, url: &str, body: &str) -> Result<String, StediError> {
+        let mut attempt: u32 = 0;
+
+        loop {
+            let wait = self.backoff * 2u32.saturating_pow(attempt);
+
+            match self.client.request(self.request(url, body)?) {
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = response.header(&HeaderName::RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.trim().parse::<u64>().ok())
+                        .map(Duration::from_secs);
+                    let response_body = response.into_body().to_string().map_err(StediError::Transport)?;
+
+                    if status.is_successful() {
+                        return Ok(response_body);
+                    }
+
+                    if status == Status::UNAUTHORIZED || status == Status::FORBIDDEN {
+                        return Err(StediError::Unauthorized(error_message(&response_body)));
+                    }
+
+                    if !is_retryable(status) || attempt >= self.max_retries {
+                        return Err(StediError::Status { status: status.into(), message: error_message(&response_body) });
+                    }
+
+                    thread::sleep(self.retry_wait(retry_after, wait));
+                },
+                Err(e) => {
+                    if attempt >= self.max_retries {
+                        return Err(StediError::Transport(e));
+                    }
+
+                    thread::sleep(wait);
+                },
+            }
+
+            attempt += 1;
+        }
+    }
+
+    pub fn map(&self, map_id: &str, input: &str) -> Result<String, StediError> {
+        let url = format!("{}/mappings/{}/map", self.mappings_base_url, map_id);
+        let body = self.post(&url, input)?;
+
+        // the mapped document is forwarded verbatim, so make sure it is json now
+        serde_json::from_str::<serde_json::Value>(&body).map_err(|_| StediError::InvalidResponse(body.clone()))?;
+
+        Ok(body)
+    }
+
+    // the raw response body; see edi_output for the X12 inside it
+    pub fn translate(&self, guide_id: &str, mapped: &str, envelope: &str) -> Result<String, StediError> {
+        let url = format!("{}/x12/from-json", self.translate_base_url);
+        let edi_request_body = format!("{{\"guideId\": \"{}\", \"input\": {}, \"envelope\": {}}}", guide_id, mapped, envelope);
+
+        self.post(&url, &edi_request_body)
+    }
+}
+
+pub fn edi_output(translate_response: &str) -> Result<String, StediError> {
+    let edi_response_json: serde_json::Value = serde_json::from_str(translate_response)
+        .map_err(|_| StediError::InvalidResponse(translate_response.to_string()))?;
+
+    match edi_response_json["output"].as_str() {
+        Some(output) if !output.is_empty() => Ok(output.to_string()),
+        _ => Err(StediError::MissingOutput(translate_response.to_string())),
+    }
+}
+
+// fallback for setups without an [envelope] section in config.toml
+pub fn read_stedi_envelope() -> Result<String, StediError> {
+    fs::read_to_string(ENVELOPE_FILE).map_err(StediError::Input)
+}
+
+// both responses are saved to responses_dir as they arrive so a failed run still leaves them behind
+pub fn make_api_call_to_stedi_for_edi_string(client: &StediClient, map_id: &str, guide_id: &str, edi_config: &str, combined: &Path, responses_dir: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let combined_json = fs::read_to_string(combined).map_err(StediError::Input)?;
+
+    let mapped = client.map(map_id, &combined_json)?;
+    fs::write(responses_dir.join(MAP_RESPONSE_FILE), &mapped)?;
+
+    // dbg!(&mapped);
+
+    let translated = client.translate(guide_id, &mapped, edi_config)?;
+    fs::write(responses_dir.join(TRANSLATE_RESPONSE_FILE), &translated)?;
+
+    Ok(edi_output(&translated)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use oxhttp::Server;
+    use oxhttp::model::Response;
+
+    use super::*;
+
+    // a local server answering with `replies` in turn (the last one repeats); returns
+    // its base url and how many requests it has seen
+    fn serve(replies: Vec<(u16, &'static str)>) -> (String, Arc<AtomicUsize>) {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&seen);
+
+        let server = Server::new(move |_| {
+            let idx = counter.fetch_add(1, Ordering::SeqCst).min(replies.len() - 1);
+            let (status, body) = replies[idx];
+            Response::builder(Status::try_from(status).unwrap()).with_body(body)
+        });
+        thread::spawn(move || server.listen(address));
+
+        while TcpStream::connect(address).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        (format!("http://{}", address), seen)
+    }
+
+    fn client(url: &str, max_retries: u32) -> StediClient {
+        StediClient::new("key", url, url, Duration::from_secs(5), max_retries, Duration::from_millis(1))
+    }
+
+    #[test]
+    fn retryable_statuses_are_retried_until_they_succeed() {
+        let (url, seen) = serve(vec![(503, "busy"), (429, "slow down"), (200, r#"{"mapped": true}"#)]);
+
+        assert_eq!(client(&url, 3).map("map", "{}").unwrap(), r#"{"mapped": true}"#);
+        assert_eq!(seen.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_the_timeout() {
+        let client = client("http://127.0.0.1", 3);
+        let wait = Duration::from_millis(1);
+
+        assert_eq!(client.retry_wait(None, wait), wait);
+        assert_eq!(client.retry_wait(Some(Duration::from_secs(2)), wait), Duration::from_secs(2));
+        assert_eq!(client.retry_wait(Some(Duration::from_secs(86_400)), wait), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn retries_stop_at_the_limit() {
+        let (url, seen) = serve(vec![(500, r#"{"message": "internal"}"#)]);
+
+        match client(&url, 2).map("map", "{}") {
+            Err(StediError::Status { status: 500, message }) => assert_eq!(message, "internal"),
+            other => panic!("expected a 500, got {:?}", other),
+        }
+        assert_eq!(seen.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let (url, seen) = serve(vec![(400, r#"{"message": "bad guide"}"#)]);
+
+        match client(&url, 3).translate("guide", "{}", "{}") {
+            Err(StediError::Status { status: 400, message }) => assert_eq!(message, "bad guide"),
+            other => panic!("expected a 400, got {:?}", other),
+        }
+        assert_eq!(seen.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn rejected_keys_are_unauthorized() {
+        let (url, _) = serve(vec![(403, r#"{"message": "forbidden"}"#)]);
+
+        assert!(matches!(client(&url, 3).map("map", "{}"), Err(StediError::Unauthorized(message)) if message == "forbidden"));
+    }
+
+    #[test]
+    fn mapped_documents_must_be_json() {
+        let (url, _) = serve(vec![(200, "not json")]);
+
+        assert!(matches!(client(&url, 0).map("map", "{}"), Err(StediError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn unreachable_servers_are_transport_errors() {
+        let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+        assert!(matches!(client(&format!("http://{}", address), 1).map("map", "{}"), Err(StediError::Transport(_))));
+    }
+
+    #[test]
+    fn translate_output_is_required() {
+        assert_eq!(edi_output(r#"{"output": "ISA*00~"}"#).unwrap(), "ISA*00~");
+        assert!(matches!(edi_output(r#"{"output": ""}"#), Err(StediError::MissingOutput(_))));
+        assert!(matches!(edi_output(r#"{"errors": []}"#), Err(StediError::MissingOutput(_))));
+        assert!(matches!(edi_output("<html>"), Err(StediError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn failures_map_to_the_error_kind_that_says_what_to_do() {
+        let kind = |e: StediError| crate::error::Error::from(e).kind();
+        let status = |status: u16| StediError::Status { status, message: String::new() };
+
+        assert_eq!(kind(status(429)), "transport");
+        assert_eq!(kind(status(503)), "transport");
+        assert_eq!(kind(status(400)), "translation");
+        assert_eq!(kind(StediError::Unauthorized(String::new())), "config");
+        assert_eq!(kind(StediError::MissingOutput(String::new())), "translation");
+        assert_eq!(kind(StediError::Transport(std::io::Error::other("down"))), "transport");
+    }
+
+    #[test]
+    fn invalid_urls_are_reported() {
+        assert!(matches!(client("not a url", 0).map("map", "{}"), Err(StediError::InvalidUrl(_))));
+    }
+}
@@ -1,31 +1,46 @@
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
-pub fn write_to_file(data: &str) -> Result<(), Box<dyn std::error::Error>> {    
-    let mut file = File::create("output.edi")?;
+pub const OUTPUT_FILE: &str = "output.edi";
 
-    let lines: Vec<&str> = data.trim_start_matches("\"").trim_end_matches("\"").split("\\n").collect();
+// stedi leaves bare N3 / N4 segments for buyers without an address; drop them
+// and take them back out of the SE segment count
+pub fn strip_empty_addresses(data: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut output = String::new();
     let mut count: i32 = 0;
 
-    // dbg!(&lines);
-
-    for line in lines {        
-        if regex::Regex::new(r"^N(3|4)$").unwrap().is_match(line) {
+    for line in data.lines() {
+        if line == "N3" || line == "N4" {
             count += 1;
             continue;
-        }        
-        if regex::Regex::new(r"^SE\*").unwrap().is_match(line) {
+        }
+        if line.starts_with("SE*") {
             let parts: Vec<&str> = line.split("*").collect();
-            let new_count = parts[1].parse::<i32>().unwrap() - count;
+            let segment_count = match parts.as_slice() {
+                [_, segment_count, _] => segment_count.parse::<i32>().ok(),
+                _ => None,
+            };
+            let new_count = segment_count.ok_or_else(|| format!("Malformed SE segment in the translated EDI: {}", line))? - count;
 
             // dbg!(&parts);
             // dbg!(&new_count);
 
-            writeln!(file, "{}*{}*{}", parts[0], new_count, parts[2])?;
+            output.push_str(format!("{}*{}*{}\n", parts[0], new_count, parts[2]).as_str());
+            // counts are per transaction set
+            count = 0;
             continue;
         }
-        writeln!(file, "{}", line)?;
+        output.push_str(line);
+        output.push('\n');
     }
 
+    Ok(output)
+}
+
+pub fn write_to_file(data: &str, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = File::create(output)?;
+    file.write_all(data.as_bytes())?;
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,28 +1,29 @@
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
-const OUTPUT_FILE: &str = "output.json";
-const SCHEMA_FILE: &str = "schema.json";
-const COMBINED_FILE: &str = "combined.json";
+pub const JSON_OUTPUT_FILE: &str = "output.json";
+pub const SCHEMA_FILE: &str = "schema.json";
+pub const COMBINED_FILE: &str = "combined.json";
 
 const DEFAULT_SCHEMA_KEY: &str = "default";
 
-pub fn to_json<T>(payload: &T) -> Result<(), Box<dyn std::error::Error>> where T: serde::Serialize {
-    let mut file = File::create(OUTPUT_FILE)?;
+pub fn to_json<T>(payload: &T, output: &Path) -> Result<(), Box<dyn std::error::Error>> where T: serde::Serialize {
+    let mut file = File::create(output)?;
     let json = serde_json::to_string_pretty(payload)?;
     file.write_all(json.as_bytes())?;
 
     Ok(())
 }
 
-pub fn combine_schema_with_output_to_json() -> Result<(), Box<dyn std::error::Error>> {
+pub fn combine_schema_with_output_to_json(output: &Path, combined: &Path) -> Result<(), Box<dyn std::error::Error>> {
     let mut schema_file = File::open(SCHEMA_FILE)?;
     let mut schema = String::new();
     schema_file.read_to_string(&mut schema)?;
 
     let mut schema_json: serde_json::Value = serde_json::from_str(&schema)?;
 
-    let mut output_file = File::open(OUTPUT_FILE)?;
+    let mut output_file = File::open(output)?;
     let mut output = String::new();
     output_file.read_to_string(&mut output)?;
 
@@ -32,8 +33,8 @@ pub fn combine_schema_with_output_to_json() -> Result<(), Box<dyn std::error::Er
 
     let output_str = serde_json::to_string_pretty(&schema_json)?;
 
-    let mut combined_file = File::create(COMBINED_FILE)?;
+    let mut combined_file = File::create(combined)?;
     combined_file.write_all(output_str.as_bytes())?;
 
     Ok(())
-}
\ No newline at end of file
+}
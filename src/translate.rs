@@ -0,0 +1,148 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::archive::RunDir;
+use crate::config::Config;
+use crate::envelope::Envelope;
+use crate::payload::Payload;
+use crate::stedi::*;
+use crate::to_edi::strip_empty_addresses;
+use crate::to_json::*;
+use crate::x12::{encode, transaction_sets};
+
+// which backend turns the payload into X12 - `backend` under [translator]
+// in config.toml, or --translator on the command line
+#[derive(Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    // map and translate through the stedi apis
+    #[default]
+    Stedi,
+    // encode locally, no network needed
+    Native,
+    // stop after combined.json is written
+    Json,
+}
+
+// the payload is already in the run directory as output.json when translate is called
+pub trait Translator {
+    fn name(&self) -> &'static str;
+
+    // files the backend reads from the working directory, archived with the run
+    fn inputs(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    // the finished interchange, or None when the backend stops before X12
+    fn translate(&self, payload: &Payload, interchange_control_number: &str, group_control_number: &str, run: &RunDir) -> Result<Option<String>, Box<dyn std::error::Error>>;
+}
+
+// the stedi mapping expects the payload merged into schema.json
+fn combine(run: &RunDir) -> Result<(), Box<dyn std::error::Error>> {
+    combine_schema_with_output_to_json(run.file(JSON_OUTPUT_FILE).as_path(), run.file(COMBINED_FILE).as_path())
+}
+
+// the envelope actually sent to stedi, kept with the run
+const STEDI_ENVELOPE_FILE: &str = "stedi_envelope.json";
+
+pub struct StediTranslator<'a> {
+    client: StediClient,
+    map_id: &'a str,
+    guide_id: &'a str,
+    envelope: Option<&'a Envelope>,
+}
+
+impl<'a> StediTranslator<'a> {
+    pub fn new(config: &'a Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let (map_id, api_key, guide_id) = config.get_stedi_params()?;
+        let (mappings_url, translate_url) = config.get_stedi_urls();
+        let (timeout, max_retries, backoff) = config.get_stedi_retry_params();
+
+        Ok(Self {
+            client: StediClient::new(api_key.expose(), mappings_url, translate_url, timeout, max_retries, backoff),
+            map_id,
+            guide_id,
+            envelope: config.get_envelope(),
+        })
+    }
+}
+
+impl Translator for StediTranslator<'_> {
+    fn name(&self) -> &'static str {
+        "stedi"
+    }
+
+    fn inputs(&self) -> Vec<PathBuf> {
+        match self.envelope {
+            Some(_) => vec![PathBuf::from(SCHEMA_FILE)],
+            None => vec![PathBuf::from(SCHEMA_FILE), PathBuf::from(ENVELOPE_FILE)],
+        }
+    }
+
+    fn translate(&self, _payload: &Payload, interchange_control_number: &str, group_control_number: &str, run: &RunDir) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        // combine payload with schema file
+        combine(run)?;
+
+        let edi_config = match self.envelope {
+            Some(envelope) => envelope.to_stedi_json(interchange_control_number, group_control_number)?,
+            None => read_stedi_envelope()?,
+        };
+        std::fs::write(run.file(STEDI_ENVELOPE_FILE), &edi_config)?;
+
+        let edi_string = make_api_call_to_stedi_for_edi_string(&self.client, self.map_id, self.guide_id, &edi_config, run.file(COMBINED_FILE).as_path(), run.path())?;
+
+        Ok(Some(strip_empty_addresses(&edi_string)?))
+    }
+}
+
+pub struct NativeTranslator<'a> {
+    envelope: &'a Envelope,
+}
+
+impl<'a> NativeTranslator<'a> {
+    pub fn new(config: &'a Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let envelope = config.get_envelope().ok_or("An [envelope] section in config.toml is required for native encoding.")?;
+
+        Ok(Self { envelope })
+    }
+}
+
+impl Translator for NativeTranslator<'_> {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn translate(&self, payload: &Payload, interchange_control_number: &str, group_control_number: &str, _run: &RunDir) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let interchange = self.envelope.wrap(transaction_sets(payload), interchange_control_number, group_control_number);
+
+        Ok(Some(encode(&interchange, &self.envelope.delimiters())?))
+    }
+}
+
+pub struct JsonTranslator;
+
+impl Translator for JsonTranslator {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn inputs(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(SCHEMA_FILE)]
+    }
+
+    fn translate(&self, _payload: &Payload, _interchange_control_number: &str, _group_control_number: &str, run: &RunDir) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        combine(run)?;
+
+        Ok(None)
+    }
+}
+
+// built before any control numbers are drawn so a misconfigured backend costs nothing
+pub fn translator(backend: Backend, config: &Config) -> Result<Box<dyn Translator + '_>, Box<dyn std::error::Error>> {
+    Ok(match backend {
+        Backend::Stedi => Box::new(StediTranslator::new(config)?),
+        Backend::Native => Box::new(NativeTranslator::new(config)?),
+        Backend::Json => Box::new(JsonTranslator),
+    })
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::read_csv::ContractData;
+
+// the subset of the X12 355 unit of measure codes we price in unless [units] allowed says otherwise
+pub const DEFAULT_UNITS_OF_MEASURE: [&str; 16] = [
+    "BG", "BO", "BX", "CA", "CS", "CT", "DZ", "EA",
+    "GR", "KT", "PK", "PR", "RL", "SH", "ST", "VI",
+];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PackDefault {
+    pub uom: Option<String>,
+    pub quantity: Option<i32>,
+}
+
+// [units] in config.toml - the fallback pack plus per part overrides, and the
+// 355 codes a partner accepts
+#[derive(Deserialize, Debug, Clone)]
+pub struct Units {
+    #[serde(default = "default_uom")]
+    pub uom: String,
+    #[serde(default = "default_quantity")]
+    pub quantity: i32,
+    #[serde(default)]
+    pub parts: HashMap<String, PackDefault>,
+    #[serde(default = "default_allowed")]
+    pub allowed: Vec<String>,
+}
+
+fn default_uom() -> String {
+    "CA".to_string()
+}
+
+fn default_quantity() -> i32 {
+    1
+}
+
+fn default_allowed() -> Vec<String> {
+    DEFAULT_UNITS_OF_MEASURE.iter().map(|code| code.to_string()).collect()
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self {
+            uom: default_uom(),
+            quantity: default_quantity(),
+            parts: HashMap::new(),
+            allowed: default_allowed(),
+        }
+    }
+}
+
+impl Units {
+    pub fn is_allowed(&self, code: &str) -> bool {
+        self.allowed.iter().any(|allowed| allowed.eq_ignore_ascii_case(code))
+    }
+
+    // csv columns win, then the part's entry in config, then the [units] fallback
+    pub fn resolve(&self, part: &str, uom: &str, quantity: Option<i32>) -> (String, i32) {
+        let part = self.parts.get(part);
+
+        let uom = match uom.trim() {
+            "" => part.and_then(|p| p.uom.clone()).unwrap_or_else(|| self.uom.clone()),
+            uom => uom.to_uppercase(),
+        };
+
+        let quantity = quantity
+            .or_else(|| part.and_then(|p| p.quantity))
+            .unwrap_or(self.quantity);
+
+        (uom, quantity)
+    }
+
+    pub fn resolve_for(&self, agreement: &ContractData) -> (String, i32) {
+        self.resolve(agreement.part.as_str(), agreement.uom.as_str(), agreement.quantity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_list_is_used_until_config_replaces_it() {
+        let units = Units::default();
+        assert!(units.is_allowed("CA") && units.is_allowed("ea"));
+        assert!(!units.is_allowed("UN"));
+
+        let units: Units = toml::from_str("allowed = [\"UN\", \"PC\"]").unwrap();
+        assert!(units.is_allowed("UN") && units.is_allowed("PC"));
+        assert!(!units.is_allowed("CA"));
+    }
+}
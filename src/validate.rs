@@ -0,0 +1,392 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::date::DateFormats;
+use crate::payload::{DealerType, IdentifierType};
+use crate::price::{r_digits, PriceFormat, MAX_R_DIGITS};
+use crate::read_csv::csv_reader;
+use crate::uom::Units;
+use crate::x12::Delimiters;
+
+const CONTRACT_COLUMNS: [&str; 6] = ["part", "price", "description", "start", "end", "purpose"];
+const BUYER_COLUMNS: [&str; 9] = ["name", "id", "address", "city", "state", "zipcode", "change", "start", "end"];
+const PURPOSES: [&str; 3] = ["add", "delete", "change"];
+
+#[derive(Debug, Serialize)]
+pub struct ValidationIssue {
+    pub file: String,
+    pub row: u64,
+    pub column: String,
+    pub value: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{} [{}] '{}' - {}", self.file, self.row, self.column, self.value, self.reason)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+// a single csv row, with its header so fields can be looked up by column name
+struct Row<'a> {
+    file: &'a str,
+    line: u64,
+    headers: &'a csv::StringRecord,
+    record: &'a csv::StringRecord,
+}
+
+impl Row<'_> {
+    fn get(&self, column: &str) -> &str {
+        self.headers.iter()
+            .position(|h| h == column)
+            .and_then(|idx| self.record.get(idx))
+            .unwrap_or("")
+    }
+}
+
+fn is_state(value: &str) -> bool {
+    value.len() == 2 && value.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn is_zipcode(value: &str) -> bool {
+    let digits = |part: &str, len: usize| part.len() == len && part.chars().all(|c| c.is_ascii_digit());
+
+    match value.split_once('-') {
+        Some((zip, plus_four)) => digits(zip, 5) && digits(plus_four, 4),
+        None => digits(value, 5) || digits(value, 9),
+    }
+}
+
+impl ValidationReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    fn add(&mut self, row: &Row, column: &str, reason: &str) {
+        self.issues.push(ValidationIssue {
+            file: row.file.to_string(),
+            row: row.line,
+            column: column.to_string(),
+            value: row.get(column).to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    // every row of the file is checked, a problem never stops the scan
+    fn check_file(&mut self, path: &Path, columns: &[&str], delimiters: &Delimiters, check_row: &dyn Fn(&mut Self, &Row)) -> Result<(), Box<dyn std::error::Error>> {
+        let file = path.display().to_string();
+        // read exactly as parse_csv reads it for the build
+        let mut reader = csv_reader(path)?;
+        let headers = reader.headers()?.clone();
+
+        for column in columns.iter().filter(|c| !headers.iter().any(|h| h == **c)) {
+            self.issues.push(ValidationIssue {
+                file: file.clone(),
+                row: 1,
+                column: column.to_string(),
+                value: "".to_string(),
+                reason: "column is missing from the header".to_string(),
+            });
+        }
+
+        for record in reader.records() {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    self.issues.push(ValidationIssue {
+                        file: file.clone(),
+                        row: e.position().map_or(0, |p| p.line()),
+                        column: "".to_string(),
+                        value: "".to_string(),
+                        reason: e.to_string(),
+                    });
+                    continue;
+                },
+            };
+
+            let row = Row {
+                file: file.as_str(),
+                line: record.position().map_or(0, |p| p.line()),
+                headers: &headers,
+                record: &record,
+            };
+
+            // any value may end up in a segment, where a delimiter would split it
+            for column in headers.iter() {
+                if let Some(delimiter) = delimiters.find_in(row.get(column)) {
+                    self.add(&row, column, &format!("contains '{}', an X12 delimiter", delimiter));
+                }
+            }
+
+            check_row(self, &row);
+        }
+
+        Ok(())
+    }
+
+    pub fn check_contract_file(&mut self, path: &Path, price_format: &PriceFormat, units: &Units, dates: &DateFormats, delimiters: &Delimiters) -> Result<(), Box<dyn std::error::Error>> {
+        let not_a_date = |column: &str| format!("{} is not a {} date", column, dates.describe());
+        // (part, description, purpose) of the first row of the current part, whose
+        // following rows are further price tiers of the same line
+        let line: RefCell<Option<(String, String, String)>> = RefCell::new(None);
+
+        self.check_file(path, &CONTRACT_COLUMNS, delimiters, &|report, row| {
+            if row.get("part").is_empty() {
+                report.add(row, "part", "part number is required");
+            }
+            let tier_of = line.borrow().clone().filter(|(part, _, _)| part == row.get("part"));
+            match tier_of {
+                Some((part, description, purpose)) => {
+                    if description != row.get("description") {
+                        report.add(row, "description", &format!("a price tier of part {} must keep the line's description '{}'", part, description));
+                    }
+                    if purpose != row.get("purpose") {
+                        report.add(row, "purpose", &format!("a price tier of part {} must keep the line's purpose '{}'", part, purpose));
+                    }
+                },
+                None => {
+                    line.replace(Some((row.get("part").to_string(), row.get("description").to_string(), row.get("purpose").to_string())));
+                },
+            }
+            match row.get("price").parse::<Decimal>() {
+                Ok(price) if r_digits(&price_format.apply(price)) > MAX_R_DIGITS => {
+                    report.add(row, "price", "price needs more than the 17 digits an X12 R element allows");
+                },
+                Ok(_) => {},
+                Err(_) => report.add(row, "price", "price is not a number"),
+            }
+            // a blank start or end runs from / to the contract dates
+            if !row.get("start").is_empty() && dates.parse(row.get("start")).is_none() {
+                report.add(row, "start", &not_a_date("start"));
+            }
+            if !row.get("end").is_empty() && dates.parse(row.get("end")).is_none() {
+                report.add(row, "end", &not_a_date("end"));
+            }
+            // whether the window fits the contract is a rule, the contract dates come from the job
+            if let (Some(start), Some(end)) = (dates.parse(row.get("start")), dates.parse(row.get("end"))) {
+                if end < start {
+                    report.add(row, "end", "end is before start");
+                }
+            }
+            if !row.get("purpose").is_empty() && !PURPOSES.contains(&row.get("purpose")) {
+                report.add(row, "purpose", "purpose must be one of add, delete or change");
+            }
+            let quantity = match row.get("quantity") {
+                "" => None,
+                quantity => match quantity.parse::<i32>() {
+                    Ok(quantity) if quantity > 0 => Some(quantity),
+                    _ => {
+                        report.add(row, "quantity", "quantity must be a whole number greater than zero");
+                        None
+                    },
+                },
+            };
+            let (uom, _) = units.resolve(row.get("part"), row.get("uom"), quantity);
+            if !units.is_allowed(uom.as_str()) {
+                report.add(row, "uom", &format!("'{}' is not an allowed unit of measure (see [units] allowed)", uom));
+            }
+        })
+    }
+
+    pub fn check_buyer_file(&mut self, path: &Path, dates: &DateFormats, delimiters: &Delimiters) -> Result<(), Box<dyn std::error::Error>> {
+        let not_a_date = |column: &str| format!("{} is not a {} date", column, dates.describe());
+
+        self.check_file(path, &BUYER_COLUMNS, delimiters, &|report, row| {
+            if row.get("name").is_empty() {
+                report.add(row, "name", "buyer name is required");
+            }
+            if row.get("id").is_empty() {
+                report.add(row, "id", "buyer id is required");
+            }
+            if !row.get("state").is_empty() && !is_state(row.get("state")) {
+                report.add(row, "state", "state must be a two letter code");
+            }
+            if !row.get("zipcode").is_empty() && !is_zipcode(row.get("zipcode")) {
+                report.add(row, "zipcode", "zipcode must be 5 or 9 digits");
+            }
+            if !row.get("start").is_empty() && dates.parse(row.get("start")).is_none() {
+                report.add(row, "start", &not_a_date("start"));
+            }
+            if !row.get("end").is_empty() && dates.parse(row.get("end")).is_none() {
+                report.add(row, "end", &not_a_date("end"));
+            }
+            if let Some(Err(e)) = Some(row.get("entity")).filter(|c| !c.is_empty()).map(|c| c.parse::<DealerType>()) {
+                report.add(row, "entity", &e);
+            }
+            if let Some(Err(e)) = Some(row.get("qualifier")).filter(|c| !c.is_empty()).map(|c| c.parse::<IdentifierType>()) {
+                report.add(row, "qualifier", &e);
+            }
+        })
+    }
+
+    pub fn print(&self) {
+        for issue in self.issues.iter() {
+            eprintln!("{}", issue);
+        }
+        eprintln!("{} problem(s) found", self.issues.len());
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = File::create(path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // a csv file in the temp directory, named after the test so tests can run in parallel
+    fn csv_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("busse-845-validate-{}-{}.csv", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn contract_issues(name: &str, contents: &str) -> Vec<(u64, String, String)> {
+        let path = csv_file(name, contents);
+        let mut report = ValidationReport::new();
+        report.check_contract_file(&path, &PriceFormat::default(), &Units::default(), &DateFormats::default(), &Delimiters::default()).unwrap();
+
+        report.issues.into_iter().map(|issue| (issue.row, issue.column, issue.value)).collect()
+    }
+
+    fn buyer_issues(name: &str, contents: &str) -> Vec<(u64, String, String)> {
+        let path = csv_file(name, contents);
+        let mut report = ValidationReport::new();
+        report.check_buyer_file(&path, &DateFormats::default(), &Delimiters::default()).unwrap();
+
+        report.issues.into_iter().map(|issue| (issue.row, issue.column, issue.value)).collect()
+    }
+
+    fn issue(row: u64, column: &str, value: &str) -> (u64, String, String) {
+        (row, column.to_string(), value.to_string())
+    }
+
+    const CONTRACT_HEADER: &str = "part,price,description,start,end,purpose\n";
+    const BUYER_HEADER: &str = "name,id,address,city,state,zipcode,change,start,end\n";
+
+    #[test]
+    fn a_clean_contract_file_has_no_issues() {
+        let contents = format!("{}775,12.00,GAUZE PAD,20230101,,add\n776,5,SPONGE,2023-01-01,2023-12-31,change\n", CONTRACT_HEADER);
+
+        assert!(contract_issues("clean", &contents).is_empty());
+    }
+
+    #[test]
+    fn missing_columns_are_reported_on_the_header_row() {
+        assert_eq!(contract_issues("header", "part,price,description,start\n775,12,GAUZE PAD,20230101\n"), vec![
+            issue(1, "end", ""),
+            issue(1, "purpose", ""),
+        ]);
+    }
+
+    #[test]
+    fn each_problem_names_its_row_and_column() {
+        let contents = format!("{}{}{}{}",
+            CONTRACT_HEADER,
+            "775,abc,GAUZE PAD,20230101,,add\n",
+            "776,5,SPONGE,2023-13-01,20230230,add\n",
+            ",5,TAPE,20230101,,remove\n",
+        );
+
+        assert_eq!(contract_issues("rows", &contents), vec![
+            issue(2, "price", "abc"),
+            issue(3, "start", "2023-13-01"),
+            issue(3, "end", "20230230"),
+            issue(4, "part", ""),
+            issue(4, "purpose", "remove"),
+        ]);
+    }
+
+    #[test]
+    fn values_are_checked_as_they_are_built() {
+        // padding is trimmed the same way the build reads it, so ` delete` is a delete
+        let contents = format!("{}775 , 12.00 ,GAUZE PAD, 20230101 ,, delete\n", CONTRACT_HEADER);
+
+        assert!(contract_issues("trimmed", &contents).is_empty());
+    }
+
+    #[test]
+    fn a_line_without_its_own_dates_is_accepted() {
+        let contents = format!("{}775,12.00,GAUZE PAD,,,add\n776,5,SPONGE,,20230630,add\n", CONTRACT_HEADER);
+
+        assert!(contract_issues("blank_dates", &contents).is_empty());
+    }
+
+    #[test]
+    fn the_repetition_separator_is_checked_when_the_envelope_has_one() {
+        let path = csv_file("repetition", &format!("{}775,12.00,GAUZE^PAD,20230101,,add\n", CONTRACT_HEADER));
+        let delimiters = Delimiters { repetition: Some('^'), ..Delimiters::default() };
+        let mut report = ValidationReport::new();
+        report.check_contract_file(&path, &PriceFormat::default(), &Units::default(), &DateFormats::default(), &delimiters).unwrap();
+
+        assert_eq!(report.issues.iter().map(|issue| (issue.row, issue.column.as_str())).collect::<Vec<_>>(), vec![(2, "description")]);
+    }
+
+    #[test]
+    fn prices_must_fit_an_r_element() {
+        let contents = format!("{}775,123456789012345.6789,GAUZE PAD,20230101,,add\n", CONTRACT_HEADER);
+
+        assert_eq!(contract_issues("digits", &contents), vec![issue(2, "price", "123456789012345.6789")]);
+    }
+
+    #[test]
+    fn end_before_start_is_reported() {
+        let contents = format!("{}775,12,GAUZE PAD,20230601,20230101,add\n", CONTRACT_HEADER);
+
+        assert_eq!(contract_issues("window", &contents), vec![issue(2, "end", "20230101")]);
+    }
+
+    #[test]
+    fn buyer_addresses_and_dates_are_checked() {
+        let contents = format!("{}{}{}",
+            BUYER_HEADER,
+            "ACME,9988,1 Main St,Hauppauge,NY,11788-1234,DIST1,20230101,\n",
+            ",,1 Main St,Hauppauge,New York,1178,DIST1,01/01/2023,\n",
+        );
+
+        assert_eq!(buyer_issues("buyers", &contents), vec![
+            issue(3, "name", ""),
+            issue(3, "id", ""),
+            issue(3, "state", "New York"),
+            issue(3, "zipcode", "1178"),
+            issue(3, "start", "01/01/2023"),
+        ]);
+    }
+
+    #[test]
+    fn the_report_is_written_as_json() {
+        let path = csv_file("report", &format!("{}775,abc,GAUZE PAD,20230101,,add\n", CONTRACT_HEADER));
+        let mut report = ValidationReport::new();
+        report.check_contract_file(&path, &PriceFormat::default(), &Units::default(), &DateFormats::default(), &Delimiters::default()).unwrap();
+
+        let json_path = path.with_extension("json");
+        report.write_json(&json_path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+
+        assert_eq!(json["issues"][0]["row"], 2);
+        assert_eq!(json["issues"][0]["column"], "price");
+        assert_eq!(json["issues"][0]["reason"], "price is not a number");
+        assert_eq!(json["issues"][0]["file"], path.display().to_string());
+    }
+}
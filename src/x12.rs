@@ -0,0 +1,274 @@
+use std::fmt;
+
+use crate::date::X12_DATE_FORMAT;
+use crate::payload::*;
+
+const TRANSACTION_SET_ID: &str = "845";
+const AGREEMENT_TYPE: &str = "PS";
+const FREE_FORM: &str = "F";
+const CONTRACT_PRICE: &str = "CON";
+const VENDOR_CATALOG: &str = "VC";
+
+#[derive(Debug, Clone)]
+pub struct Delimiters {
+    pub element: char,
+    pub sub_element: char,
+    pub segment: char,
+    // ISA11 from 005010 on; 004010 has no repetition separator
+    pub repetition: Option<char>,
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Self {
+            element: '*',
+            sub_element: '>',
+            segment: '~',
+            repetition: None,
+        }
+    }
+}
+
+impl Delimiters {
+    pub fn find_in(&self, value: &str) -> Option<char> {
+        value.chars().find(|c| *c == self.element || *c == self.sub_element || *c == self.segment || Some(*c) == self.repetition)
+    }
+}
+
+// a value that would split or end its segment early, e.g. a description holding the element separator
+#[derive(Debug)]
+pub struct EncodeError {
+    pub segment: String,
+    pub position: usize,
+    pub value: String,
+    pub delimiter: char,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{:02} '{}' contains the delimiter '{}'", self.segment, self.position, self.value, self.delimiter)
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub id: String,
+    pub elements: Vec<String>,
+}
+
+impl Segment {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            elements: Vec::new(),
+        }
+    }
+
+    pub fn element(mut self, value: &str) -> Self {
+        self.elements.push(value.to_string());
+        self
+    }
+
+    // trailing empty elements are dropped, as X12 requires
+    pub fn encode(&self, delimiters: &Delimiters) -> Result<String, EncodeError> {
+        let used = self.elements.iter().rposition(|e| !e.is_empty()).map_or(0, |idx| idx + 1);
+
+        let mut encoded = self.id.clone();
+        for (idx, element) in self.elements[..used].iter().enumerate() {
+            // ISA11 and ISA16 are the repetition and sub-element separators themselves
+            let found = match self.id == "ISA" && (idx == 10 || idx == 15) {
+                true => element.chars().find(|c| *c == delimiters.element || *c == delimiters.segment),
+                false => delimiters.find_in(element),
+            };
+            if let Some(delimiter) = found {
+                return Err(EncodeError {
+                    segment: self.id.clone(),
+                    position: idx + 1,
+                    value: element.clone(),
+                    delimiter,
+                });
+            }
+
+            encoded.push(delimiters.element);
+            encoded.push_str(element);
+        }
+        encoded.push(delimiters.segment);
+
+        Ok(encoded)
+    }
+}
+
+fn dtm(datetime: &Datetime) -> Segment {
+    Segment::new("DTM")
+        .element(&datetime.datetime_type)
+        .element(&datetime.datetime.format(X12_DATE_FORMAT).to_string())
+}
+
+fn reference(reference: &Reference) -> Option<Segment> {
+    if reference.reference.is_empty() {
+        return None;
+    }
+
+    Some(Segment::new("REF")
+        .element(&reference.reference_type)
+        .element(&reference.reference))
+}
+
+fn dealer_segments(dealer: &Dealer) -> Vec<Segment> {
+    let mut segments = vec![Segment::new("N1")
+        .element(&dealer.group_type)
+        .element(&dealer.group_name)
+        .element(&dealer.id_type)
+        .element(&dealer.id)];
+
+    if !dealer.address.addr1.is_empty() {
+        segments.push(Segment::new("N3").element(&dealer.address.addr1));
+    }
+
+    if !dealer.address.city.is_empty() || !dealer.address.state.is_empty() || !dealer.address.zip.is_empty() {
+        segments.push(Segment::new("N4")
+            .element(&dealer.address.city)
+            .element(&dealer.address.state)
+            .element(&dealer.address.zip));
+    }
+
+    segments.extend(dealer.references.iter().filter_map(reference));
+    segments.extend(dealer.datetimes.iter().map(dtm));
+
+    segments
+}
+
+fn agreement_segments(agreement: &Agreement) -> Vec<Segment> {
+    let mut lin = Segment::new("LIN").element(&agreement.line_number.to_string());
+    for line in &agreement.line_information {
+        lin = lin.element(VENDOR_CATALOG).element(&line.item_id);
+    }
+
+    let mut segments = vec![lin];
+
+    for detail in &agreement.details {
+        segments.push(Segment::new("PID")
+            .element(FREE_FORM)
+            .element("")
+            .element("")
+            .element("")
+            .element(&detail.description));
+    }
+
+    // the line change code (AI/DI/CI) travels as a mutually defined reference
+    segments.push(Segment::new("REF")
+        .element(&ReferenceType::MutuallyDefined.to_string())
+        .element(&agreement.reference));
+
+    for pricing in &agreement.pricing {
+        segments.push(Segment::new("CTP")
+            .element("")
+            .element(CONTRACT_PRICE)
+            .element(&pricing.price.normalize().to_string())
+            .element(&pricing.quantity.to_string())
+            .element(&pricing.uom));
+        segments.extend(pricing.datetimes.iter().map(dtm));
+    }
+
+    segments
+}
+
+pub fn transaction_set(document: &Document, contract: &Contract) -> Vec<Segment> {
+    let control_number = format!("{:04}", document.control_number);
+
+    let mut segments = vec![
+        Segment::new("ST").element(TRANSACTION_SET_ID).element(&control_number),
+        Segment::new("BCT")
+            .element(AGREEMENT_TYPE)
+            .element(&contract.contract_id)
+            .element("")
+            .element("")
+            .element("")
+            .element("")
+            .element("")
+            .element("")
+            .element("")
+            .element(&document.document_type),
+    ];
+
+    segments.extend(document.datetimes.iter().map(dtm));
+    segments.extend(contract.references.iter().filter_map(reference));
+
+    for dealer in &contract.dealers {
+        segments.extend(dealer_segments(dealer));
+    }
+
+    for agreement in &contract.agreements {
+        segments.extend(agreement_segments(agreement));
+    }
+
+    // SE01 counts every segment from ST through SE inclusive
+    let count = segments.len() + 1;
+    segments.push(Segment::new("SE").element(&count.to_string()).element(&control_number));
+
+    segments
+}
+
+// one transaction set is built per contract in each document
+pub fn transaction_sets(payload: &Payload) -> Vec<Vec<Segment>> {
+    payload.documents.iter()
+        .flat_map(|document| document.contracts.iter().map(move |contract| transaction_set(document, contract)))
+        .collect()
+}
+
+pub fn encode(segments: &[Segment], delimiters: &Delimiters) -> Result<String, EncodeError> {
+    let mut edi = String::new();
+
+    for segment in segments {
+        edi.push_str(&segment.encode(delimiters)?);
+        edi.push('\n');
+    }
+
+    Ok(edi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_empty_elements_are_dropped() {
+        let segment = Segment::new("CTP").element("").element("CON").element("12").element("").element("");
+
+        assert_eq!(segment.encode(&Delimiters::default()).unwrap(), "CTP**CON*12~");
+    }
+
+    #[test]
+    fn values_holding_a_delimiter_are_rejected() {
+        for value in ["GAUZE PAD 4*4", "GAUZE>PAD", "GAUZE~PAD"] {
+            let error = Segment::new("PID").element("F").element("").element("").element("").element(value)
+                .encode(&Delimiters::default())
+                .unwrap_err();
+
+            assert_eq!((error.segment.as_str(), error.position, error.value.as_str()), ("PID", 5, value));
+        }
+    }
+
+    #[test]
+    fn isa16_may_hold_the_sub_element_separator() {
+        let mut isa = Segment::new("ISA");
+        for _ in 0..15 {
+            isa = isa.element("X");
+        }
+
+        assert!(isa.clone().element(">").encode(&Delimiters::default()).is_ok());
+        assert!(isa.element("*").encode(&Delimiters::default()).is_err());
+    }
+
+    #[test]
+    fn the_repetition_separator_is_a_delimiter_once_set() {
+        let segment = Segment::new("PID").element("F").element("").element("").element("").element("GAUZE^PAD");
+        assert!(segment.encode(&Delimiters::default()).is_ok());
+
+        let delimiters = Delimiters { repetition: Some('^'), ..Delimiters::default() };
+        let error = segment.encode(&delimiters).unwrap_err();
+        assert_eq!((error.position, error.delimiter), (5, '^'));
+    }
+}
@@ -0,0 +1,128 @@
+// the stedi client, and a whole send, against the bundled stub server replaying the
+// recorded responses in stub/
+
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use busse_845_edi_v2::read_x12::parse_x12;
+use busse_845_edi_v2::stedi::{edi_output, StediClient, StediError};
+
+// the stub is stopped when the test ends, passed or not
+struct Stub {
+    child: Child,
+    url: String,
+}
+
+impl Drop for Stub {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn start_stub() -> Stub {
+    let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_stedi_stub"))
+        .args(["-r", concat!(env!("CARGO_MANIFEST_DIR"), "/stub"), "-a", address.to_string().as_str()])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stub = Stub { child, url: format!("http://{}", address) };
+
+    for _ in 0..500 {
+        if TcpStream::connect(address).is_ok() {
+            return stub;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("the stub did not start listening on {}", address);
+}
+
+fn client(url: &str) -> StediClient {
+    StediClient::new("key", url, url, Duration::from_secs(5), 0, Duration::from_millis(1))
+}
+
+#[test]
+fn map_and_translate_replay_the_recorded_responses() {
+    let stub = start_stub();
+    let client = client(&stub.url);
+
+    let mapped = client.map("any-map", "{}").unwrap();
+    assert!(mapped.contains("\"catalog_number_02\": \"C100\""));
+
+    let translated = client.translate("guide", mapped.as_str(), "{}").unwrap();
+    let edi = edi_output(translated.as_str()).unwrap();
+    assert!(edi.starts_with("ISA*"));
+    assert!(edi.contains("ST*845*0001~"));
+}
+
+#[test]
+fn unknown_endpoints_are_not_found() {
+    let stub = start_stub();
+    let client = StediClient::new("key", format!("{}/elsewhere", stub.url).as_str(), stub.url.as_str(), Duration::from_secs(5), 0, Duration::from_millis(1));
+
+    assert!(matches!(client.map("map", "{}"), Err(StediError::Status { status: 404, .. })));
+}
+
+// a working directory with config.toml, schema.json and one contract, pointed at the stub
+fn workspace(stub_url: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("busse-845-send-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("data").join("contracts")).unwrap();
+    fs::create_dir_all(dir.join("data").join("buyers")).unwrap();
+
+    fs::write(dir.join("config.toml"), format!(r#"
+[paths]
+base = "data/"
+buyers = "buyers"
+contracts = "contracts"
+
+[stedi]
+map_id = "map"
+api_key = "key"
+guide_id = "guide"
+mappings_url = "{url}"
+translate_url = "{url}"
+max_retries = 0
+
+[envelope]
+sender_qualifier = "ZZ"
+sender_id = "BUSSE"
+receiver_qualifier = "01"
+receiver_id = "123456789"
+application_sender = "BUSSE"
+application_receiver = "PARTNER"
+usage_indicator = "T"
+"#, url = stub_url)).unwrap();
+    fs::write(dir.join("schema.json"), r#"{"default":[]}"#).unwrap();
+    fs::write(dir.join("data").join("contracts").join("C100.csv"), "part,price,description,start,end,purpose\n775,12.345,GAUZE PAD,20230101,,add\n").unwrap();
+    fs::write(dir.join("data").join("buyers").join("b1.csv"), "name,id,address,city,state,zipcode,change,start,end\nPREMIER HOSPITALS,123456,,,,,,,\n").unwrap();
+
+    dir
+}
+
+#[test]
+fn send_through_the_stub_writes_edi_the_tool_parses_back() {
+    let stub = start_stub();
+    let dir = workspace(&stub.url);
+    let cli = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_busse-845-edi-v2")).current_dir(&dir).args(args).output().unwrap();
+
+    let send = cli(&["--config", "config.toml", "send", "-c", "C100", "-b", "b1", "-s", "20230101", "-e", "20231231", "-p", "new", "--translator", "stedi"]);
+    assert!(send.status.success(), "{}", String::from_utf8_lossy(&send.stderr));
+
+    // the path of the finished output.edi is the last line printed
+    let stdout = String::from_utf8(send.stdout).unwrap();
+    let output_edi = dir.join(stdout.lines().last().unwrap().trim());
+
+    let payload = parse_x12(fs::read_to_string(&output_edi).unwrap().as_str()).unwrap();
+    assert_eq!(payload.documents[0].contracts[0].contract_id, "C100");
+
+    let parse = cli(&["parse", output_edi.to_str().unwrap()]);
+    assert!(parse.status.success(), "{}", String::from_utf8_lossy(&parse.stderr));
+
+    let _ = fs::remove_dir_all(&dir);
+}